@@ -0,0 +1,127 @@
+// Play the day15 warehouse by hand: `cargo run --bin day15_play -- <input> [--scale WxH]`
+// arrows or wasd move the robot, u / r undo and redo, q or Esc quits
+use std::{env, fs, io::{self, Write}, panic};
+
+use aoc24::day15::{Direction, Replay, Scale, Warehouse};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    terminal::{self, ClearType},
+};
+
+fn parse_scale(arg: &str) -> Scale {
+    let (x, y) = arg.split_once('x').expect("Scale must look like 2x1");
+    let scale = Scale { x: x.parse().expect("Invalid scale"), y: y.parse().expect("Invalid scale") };
+    assert!(scale.x >= 1 && scale.y >= 1, "Scale must be at least 1x1");
+    scale
+}
+
+fn read_args() -> (String, Scale) {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut scale = Scale { x: 1, y: 1 };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wide" => scale = Scale { x: 2, y: 1 },
+            "--scale" => scale = parse_scale(&args.next().expect("Missing value for --scale")),
+            _ => path = Some(arg),
+        }
+    }
+    (path.expect("Usage: day15_play <input> [--wide | --scale WxH]"), scale)
+}
+
+fn draw(out: &mut impl Write, replay: &Replay, status: &str) -> io::Result<()> {
+    queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+    // raw mode does not turn \n into \r\n
+    for line in replay.warehouse().to_string().lines() {
+        write!(out, "{}\r\n", line)?;
+    }
+    write!(out, "\r\nGPS sum: {}   moves: {}\r\n", replay.warehouse().gps_sum(), replay.history().len())?;
+    write!(out, "{}\r\n", status)?;
+    write!(out, "arrows/wasd move, u undo, r redo, q quit\r\n")?;
+    out.flush()
+}
+
+fn key_direction(code: KeyCode) -> Option<Direction> {
+    match code {
+        KeyCode::Up | KeyCode::Char('w') => Some(Direction::Up),
+        KeyCode::Down | KeyCode::Char('s') => Some(Direction::Down),
+        KeyCode::Left | KeyCode::Char('a') => Some(Direction::Left),
+        KeyCode::Right | KeyCode::Char('d') => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+fn play(out: &mut impl Write, mut replay: Replay) -> io::Result<()> {
+    let mut status = String::new();
+    loop {
+        draw(out, &replay, &status)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        status = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('u') => match replay.undo() {
+                Some(record) => format!("undid {:?}", record.dir),
+                None => "nothing to undo".to_string(),
+            },
+            KeyCode::Char('r') => match replay.redo() {
+                Some(record) => format!("redid {:?}", record.dir),
+                None => "nothing to redo".to_string(),
+            },
+            code => match key_direction(code) {
+                Some(dir) => match &replay.step(dir).pushed {
+                    Some(pushed) if pushed.is_empty() => format!("moved {:?}", dir),
+                    Some(pushed) => format!("moved {:?}, pushed {} crate(s)", dir, pushed.len()),
+                    None => format!("blocked moving {:?}", dir),
+                },
+                None => status,
+            },
+        };
+    }
+}
+
+// raw mode and the alternate screen stay on until this is dropped, also when `play` panics
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        // restore before the panic message is printed, or it ends up on the alternate screen
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        Ok(guard)
+    }
+}
+
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn main() -> io::Result<()> {
+    let (path, scale) = read_args();
+    let input = fs::read_to_string(&path)?.replace("\r\n", "\n");
+    // a puzzle input also holds the move list after a blank line, only the map is needed here
+    let map = input.split("\n\n").next().unwrap_or_default();
+    let warehouse = Warehouse::parse(map.trim_end()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let replay = Replay::new(warehouse.scaled(scale));
+
+    let _guard = TerminalGuard::enter()?;
+    play(&mut io::stdout(), replay)
+}
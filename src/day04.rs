@@ -1,6 +1,9 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 
 const XMAS: &str = "XMAS";
+const X_MAS: &str = "M.S
+.A.
+M.S";
 
 const DIRECTIONS: &[(isize, isize)] = &[
     (-1, 0),  // Up
@@ -19,89 +22,132 @@ fn parse_inputs(input: &str) -> Vec<Vec<char>> {
         
 }
 
-fn count_xmas(inp: &Vec<Vec<char>>, ) -> usize {
-    let rows = inp.len();
-    let cols = inp[0].len();
-    let mut count = 0;
-    let word_chars = XMAS.chars().collect::<Vec<char>>();
-    for i in 0..rows {
-        for j in 0..cols {
-            for &(dx, dy) in DIRECTIONS {
-                if can_match(&inp, &word_chars, i, j, dx, dy) {
-                    count += 1;
-                }
-            }
-        }
-    }
-    count
+// cells of a 2D pattern as (row, column, char) offsets from the first cell;
+// `.` in a parsed pattern is a wildcard and does not become a cell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stencil {
+    cells: Vec<(isize, isize, char)>,
 }
 
-fn can_match(grid: &[Vec<char>], word: &[char], start_r: usize, start_c: usize, dx: isize, dy: isize) -> bool {
-    let rows = grid.len() as isize;
-    let cols = grid[0].len() as isize;
-    let word_len = word.len() as isize;
-
-    let mut r = start_r as isize;
-    let mut c = start_c as isize;
-
-    for i in 0..word_len {
-        // Check boundary
-        if r < 0 || r >= rows || c < 0 || c >= cols {
-            return false; 
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordMatch {
+    pub start: (usize, usize),
+    pub dir: (isize, isize),
+}
 
-        // Check character match
-        if grid[r as usize][c as usize] != word[i as usize] {
-            return false;
+impl Stencil {
+    pub fn parse(pattern: &str) -> Stencil {
+        let mut cells = Vec::new();
+        for (r, line) in pattern.lines().enumerate() {
+            for (c, ch) in line.chars().enumerate() {
+                if ch != '.' {
+                    cells.push((r as isize, c as isize, ch));
+                }
+            }
         }
+        Stencil::from_cells(cells)
+    }
 
-        // Move to the next character in the given direction
-        r += dx;
-        c += dy;
+    pub fn word(word: &str, (dr, dc): (isize, isize)) -> Stencil {
+        Stencil::from_cells(word.chars().enumerate().map(|(i, ch)| (i as isize * dr, i as isize * dc, ch)).collect())
     }
 
-    true
-}
+    fn from_cells(cells: Vec<(isize, isize, char)>) -> Stencil {
+        let (r0, c0) = cells.first().map_or((0, 0), |&(r, c, _)| (r, c));
+        Stencil { cells: cells.into_iter().map(|(r, c, ch)| (r - r0, c - c0, ch)).collect() }
+    }
 
-fn count_x_mas(grid: &[Vec<char>]) -> usize {
-    let rows = grid.len();
-    let cols = grid[0].len();
-    let mut count = 0;
+    fn rotated(&self) -> Stencil {
+        Stencil { cells: self.cells.iter().map(|&(r, c, ch)| (c, -r, ch)).collect() }
+    }
 
-    for i in 1..rows-1 {
-        for j in 1..cols-1 {
-            if grid[i][j] == 'A' {
-                // Check the diagonals
-                let top_left = grid[i-1][j-1];
-                let bottom_right = grid[i+1][j+1];
-                let top_right = grid[i-1][j+1];
-                let bottom_left = grid[i+1][j-1];
+    fn mirrored(&self) -> Stencil {
+        Stencil { cells: self.cells.iter().map(|&(r, c, ch)| (r, -c, ch)).collect() }
+    }
 
-                let diag1_valid = (top_left == 'M' && bottom_right == 'S') 
-                                || (top_left == 'S' && bottom_right == 'M');
+    // the cells shifted to the origin and sorted, two stencils with the same shape match the same cells
+    fn shape(&self) -> Vec<(isize, isize, char)> {
+        let min_r = self.cells.iter().map(|c| c.0).min().unwrap_or(0);
+        let min_c = self.cells.iter().map(|c| c.1).min().unwrap_or(0);
+        let mut shape = self.cells.iter().map(|&(r, c, ch)| (r - min_r, c - min_c, ch)).collect::<Vec<_>>();
+        shape.sort();
+        shape
+    }
 
+    // all distinct rotations and reflections, the stencil itself first
+    pub fn symmetries(&self) -> Vec<Stencil> {
+        let mut variants: Vec<Stencil> = Vec::new();
+        for base in [self.clone(), self.mirrored()] {
+            let mut variant = base;
+            for _ in 0..4 {
+                if variants.iter().all(|v| v.shape() != variant.shape()) {
+                    variants.push(variant.clone());
+                }
+                variant = variant.rotated();
+            }
+        }
+        variants
+    }
 
-                let diag2_valid = (top_right == 'M' && bottom_left == 'S')
-                                || (top_right == 'S' && bottom_left == 'M');
+    // an empty stencil has nothing to match and is found nowhere
+    pub fn matches_at(&self, grid: &[Vec<char>], r: usize, c: usize) -> bool {
+        !self.cells.is_empty() && self.cells.iter().all(|&(dr, dc, ch)| {
+            let (nr, nc) = (r as isize + dr, c as isize + dc);
+            nr >= 0 && nc >= 0 && grid.get(nr as usize).and_then(|row| row.get(nc as usize)) == Some(&ch)
+        })
+    }
 
-                if diag1_valid && diag2_valid {
-                    count += 1;
+    // the grid positions of the first cell wherever the stencil matches
+    pub fn find(&self, grid: &[Vec<char>]) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        for (r, row) in grid.iter().enumerate() {
+            for c in 0..row.len() {
+                if self.matches_at(grid, r, c) {
+                    found.push((r, c));
                 }
             }
         }
+        found
     }
+}
 
-    count
+// every match of any rotation or reflection of the pattern, as (variant, position of its first cell)
+pub fn find_pattern(grid: &[Vec<char>], pattern: &Stencil) -> Vec<(Stencil, (usize, usize))> {
+    pattern
+        .symmetries()
+        .into_iter()
+        .flat_map(|variant| variant.find(grid).into_iter().map(move |pos| (variant.clone(), pos)))
+        .collect()
+}
+
+// the word read in any of the 8 directions; a palindrome read backwards is the same occurrence
+// and is only reported once
+pub fn find_word(grid: &[Vec<char>], word: &str) -> Vec<WordMatch> {
+    let mut variants = Stencil::word(word, (0, 1)).symmetries();
+    for diagonal in Stencil::word(word, (1, 1)).symmetries() {
+        if variants.iter().all(|v| v.shape() != diagonal.shape()) {
+            variants.push(diagonal);
+        }
+    }
+    let mut matches = Vec::new();
+    for variant in variants {
+        let dir = variant.cells.get(1).map_or((0, 1), |&(r, c, _)| (r, c));
+        for start in variant.find(grid) {
+            matches.push(WordMatch { start, dir });
+        }
+    }
+    matches.sort_by_key(|m| (m.start, DIRECTIONS.iter().position(|&d| d == m.dir)));
+    matches
 }
 
 #[aoc(day4, part1)]
-fn part1(inp: &Vec<Vec<char>>) -> usize {
-    count_xmas(inp)
+fn part1(inp: &[Vec<char>]) -> usize {
+    find_word(inp, XMAS).len()
 }
 
 #[aoc(day4, part2)]
-fn part2(inp: &Vec<Vec<char>>) -> usize {
-    count_x_mas(inp)
+fn part2(inp: &[Vec<char>]) -> usize {
+    find_pattern(inp, &Stencil::parse(X_MAS)).len()
 }
 
 #[cfg(test)]
@@ -130,4 +176,27 @@ MXMXAXMASX";
         let inp = parse_inputs(TEST_INPUT);
         assert_eq!(part2(&inp), 9);
     }
+
+    #[test]
+    fn test_find_word() {
+        let inp = parse_inputs("XMAS.\n.M...\n..A..\n...S.\nSAMX.");
+        assert_eq!(find_word(&inp, "XMAS"), vec![
+            WordMatch { start: (0, 0), dir: (0, 1) },
+            WordMatch { start: (0, 0), dir: (1, 1) },
+            WordMatch { start: (4, 3), dir: (0, -1) },
+        ]);
+        // both readings of a palindrome are one occurrence
+        assert_eq!(find_word(&parse_inputs("ABA"), "ABA").len(), 1);
+        assert_eq!(find_word(&inp, "M").len(), 3);
+        assert!(find_word(&inp, "").is_empty());
+        assert!(Stencil::parse("..\n..").find(&inp).is_empty());
+    }
+
+    #[test]
+    fn test_stencil() {
+        let inp = parse_inputs(TEST_INPUT);
+        assert_eq!(Stencil::parse(X_MAS).symmetries().len(), 4);
+        assert_eq!(Stencil::word("XMAS", (0, 1)).symmetries().len(), 4);
+        assert_eq!(Stencil::parse(X_MAS).find(&inp), vec![(0, 1), (2, 1)]);
+    }
 }
\ No newline at end of file
@@ -1,15 +1,28 @@
-use std::collections::HashSet;
-use itertools::iproduct;
+use std::collections::HashMap;
+
 use pathfinding::prelude::Matrix;
+use num::Integer;
+use rayon::prelude::*;
 use aoc_runner_derive::{aoc, aoc_generator};
 
+// `pos` is (row, column) and `dir` the (row, column) step, so (-1, 0) faces up
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
-struct Guard {
-    pos: (usize, usize),
-    dir: (isize, isize),
+pub struct Guard {
+    pub pos: (usize, usize),
+    pub dir: (isize, isize),
 }
 
 impl Guard {
+    fn from_char(c: char, pos: (usize, usize)) -> Option<Guard> {
+        let dir = match c {
+            '^' => (-1, 0),
+            '>' => (0, 1),
+            'v' => (1, 0),
+            '<' => (0, -1),
+            _ => return None,
+        };
+        Some(Guard { pos, dir })
+    }
     fn turn_right(&mut self) {
        match self.dir {
               (0, -1) => self.dir = (-1, 0),
@@ -47,61 +60,368 @@ fn walk_path(grid: &Matrix<char>, mut guard: Guard) -> Matrix<bool>{
 }
 
 #[aoc_generator(day6)]
-fn read_inputs(input: &str) -> Matrix<char> {
+pub fn read_inputs(input: &str) -> Matrix<char> {
     let v = input.lines().map(|l| l.chars().collect::<Vec<char>>()).collect::<Vec<Vec<char>>>();
     Matrix::from_rows(v).unwrap()
 }
 
-fn get_start_pos(grid: &Matrix<char>) -> (usize, usize) {
-    grid.keys().find(|&(r, c)| grid[(r, c)] == '^').unwrap()
+// every guard on the map in row major order
+pub fn find_guards(grid: &Matrix<char>) -> Vec<Guard> {
+    grid.keys().filter_map(|pos| Guard::from_char(grid[pos], pos)).collect()
 }
 
-#[aoc(day6, part1)]
-fn part1(inp: &Matrix<char>) -> usize {
+// the map with all guard starts cleared, and the guards standing on them
+fn prepare_all(inp: &Matrix<char>) -> (Matrix<char>, Vec<Guard>) {
     let mut grid = inp.clone();
-    let (start_row, start_col) = get_start_pos(&grid);
+    let guards = find_guards(&grid);
+    for guard in &guards {
+        grid[guard.pos] = '.';
+    }
+    (grid, guards)
+}
 
-    grid[(start_row, start_col)] = '.';
+// the single guard puzzle: the first guard on the map
+fn prepare(inp: &Matrix<char>) -> (Matrix<char>, Guard) {
+    let (grid, guards) = prepare_all(inp);
+    (grid, *guards.first().expect("No guard on the map"))
+}
 
-    let guard = Guard { pos: (start_row, start_col), dir: (-1, 0) };
+#[aoc(day6, part1)]
+fn part1(inp: &Matrix<char>) -> usize {
+    let (grid, guard) = prepare(inp);
     let visited = walk_path(&grid, guard);
     visited.values().filter(|&v| *v).count()
 }
 
-fn count_loops(inp: &Matrix<char>) -> usize {
-    let mut count = 0;
-    let mut grid = inp.clone();
-    let (start_row, start_col) = get_start_pos(&grid);
-    grid[(start_row, start_col)] = '.';
+// every state of the guard until it leaves the map, a turn shows up as a second state on the same cell
+pub fn guard_route(inp: &Matrix<char>) -> Vec<Guard> {
+    let (grid, guard) = prepare(inp);
+    route_until_exit_or_loop(&grid, guard).0
+}
+
+// steps the guard until it walks off the map or repeats a state;
+// on a repeat the second value is the index in the route where the cycle starts
+fn route_until_exit_or_loop(grid: &Matrix<char>, mut guard: Guard) -> (Vec<Guard>, Option<usize>) {
+    let mut route = vec![guard];
+    let mut seen = HashMap::from([(guard, 0)]);
+    while let Some(c) = grid.get(guard.next_pos()) {
+        match c {
+            '#' => guard.turn_right(),
+            _ => guard.make_move(),
+        }
+        if let Some(&start) = seen.get(&guard) {
+            return (route, Some(start));
+        }
+        seen.insert(guard, route.len());
+        route.push(guard);
+    }
+    (route, None)
+}
+
+// up, right, down, left: turning right is the next index
+const DIRS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+fn dir_index(dir: (isize, isize)) -> usize {
+    DIRS.iter().position(|&d| d == dir).expect("Invalid direction")
+}
 
-    let start_guard = Guard { pos: (start_row, start_col), dir: (-1, 0) };
+fn offset(pos: (usize, usize), dir: (isize, isize), steps: usize) -> (usize, usize) {
+    (
+        (pos.0 as isize + dir.0 * steps as isize) as usize,
+        (pos.1 as isize + dir.1 * steps as isize) as usize,
+    )
+}
 
-    let visited = walk_path(&grid, start_guard);
+// how many steps `target` lies ahead of `pos` when walking in `dir`, if it is ahead at all
+fn steps_ahead(pos: (usize, usize), dir: (isize, isize), target: (usize, usize)) -> Option<usize> {
+    let dr = target.0 as isize - pos.0 as isize;
+    let dc = target.1 as isize - pos.1 as isize;
+    match dir {
+        (0, d) if dr == 0 && dc * d > 0 => Some(dc.unsigned_abs()),
+        (d, 0) if dc == 0 && dr * d > 0 => Some(dr.unsigned_abs()),
+        _ => None,
+    }
+}
 
-    for (r, c) in iproduct!(0..inp.rows, 0..inp.columns) {
-        if grid[(r, c)] == '#' || !visited[(r, c)] || (r == start_row && c == start_col) {
+// for every cell and facing, the cell the guard stops at in front of the next obstacle,
+// `None` when it walks off the map instead
+struct JumpTable {
+    columns: usize,
+    stops: Vec<[Option<(usize, usize)>; 4]>,
+}
+
+impl JumpTable {
+    fn new(grid: &Matrix<char>) -> JumpTable {
+        let mut stops = vec![[None; 4]; grid.rows * grid.columns];
+        for (d, &dir) in DIRS.iter().enumerate() {
+            // sweep against the walking direction so the obstacle ahead is always already seen
+            let (back_rows, back_cols) = (dir.0 > 0, dir.1 > 0);
+            for r in 0..grid.rows {
+                for c in 0..grid.columns {
+                    let r = if back_rows { grid.rows - 1 - r } else { r };
+                    let c = if back_cols { grid.columns - 1 - c } else { c };
+                    let stop = match grid.get(offset((r, c), dir, 1)) {
+                        None => None,
+                        Some('#') => Some((r, c)),
+                        Some(_) => {
+                            let (nr, nc) = offset((r, c), dir, 1);
+                            stops[nr * grid.columns + nc][d]
+                        }
+                    };
+                    stops[r * grid.columns + c][d] = stop;
+                }
+            }
+        }
+        JumpTable { columns: grid.columns, stops }
+    }
+
+    // where the guard stops when walking from `pos`, with `extra` as an additional obstacle
+    fn stop(&self, pos: (usize, usize), d: usize, extra: (usize, usize)) -> Option<(usize, usize)> {
+        let stop = self.stops[pos.0 * self.columns + pos.1][d];
+        match steps_ahead(pos, DIRS[d], extra) {
+            Some(k) if stop.is_none_or(|s| steps_ahead(pos, DIRS[d], s).unwrap_or(0) >= k) => {
+                Some(offset(pos, DIRS[d], k - 1))
+            }
+            _ => stop,
+        }
+    }
+}
+
+// every cell of the guard route except the start, with the guard state right before first stepping on it;
+// a guard that loops without any extra obstacle stops once its position and direction repeat
+fn first_entries(grid: &Matrix<char>, mut guard: Guard) -> Vec<((usize, usize), Guard)> {
+    let mut seen = Matrix::new(grid.rows, grid.columns, false);
+    seen[guard.pos] = true;
+    let mut states = vec![false; grid.rows * grid.columns * 4];
+    let mut entries = Vec::new();
+    while let Some(c) = grid.get(guard.next_pos()) {
+        let state = (guard.pos.0 * grid.columns + guard.pos.1) * 4 + dir_index(guard.dir);
+        if states[state] {
+            break;
+        }
+        states[state] = true;
+        if *c == '#' {
+            guard.turn_right();
             continue;
         }
-        grid[(r, c)] = '#';
-        let mut g = start_guard;
+        let next = guard.next_pos();
+        if !seen[next] {
+            seen[next] = true;
+            entries.push((next, guard));
+        }
+        guard.make_move();
+    }
+    entries
+}
+
+// the guard teleports from turn to turn, a loop shows up as a repeated stop;
+// `seen` is stamped per candidate so it never needs clearing
+fn loops_with_obstacle(jumps: &JumpTable, mut guard: Guard, obstacle: (usize, usize), seen: &mut [u32], stamp: u32) -> bool {
+    let mut d = dir_index(guard.dir);
+    loop {
+        let Some(stop) = jumps.stop(guard.pos, d, obstacle) else {
+            return false;
+        };
+        let state = (stop.0 * jumps.columns + stop.1) * 4 + d;
+        if seen[state] == stamp {
+            return true;
+        }
+        seen[state] = stamp;
+        guard.pos = stop;
+        d = (d + 1) % 4;
+    }
+}
+
+// the positions where a single new obstruction traps the guard in a loop, in row major order
+pub fn loop_obstructions(inp: &Matrix<char>) -> Vec<(usize, usize)> {
+    let (grid, start_guard) = prepare(inp);
+    let jumps = JumpTable::new(&grid);
+    let entries = first_entries(&grid, start_guard);
+
+    let mut obstructions = entries
+        .par_iter()
+        .enumerate()
+        .map_init(
+            || vec![0; grid.rows * grid.columns * 4],
+            |seen, (i, &(obstacle, guard))| {
+                loops_with_obstacle(&jumps, guard, obstacle, seen, i as u32 + 1).then_some(obstacle)
+            },
+        )
+        .flatten()
+        .collect::<Vec<_>>();
+    obstructions.sort();
+    obstructions
+}
+
+// the states the guard keeps repeating once the obstruction is placed, `None` if it still gets out
+pub fn loop_cycle(inp: &Matrix<char>, obstruction: (usize, usize)) -> Option<Vec<Guard>> {
+    let (mut grid, guard) = prepare(inp);
+    grid[obstruction] = '#';
+    let (mut route, cycle_start) = route_until_exit_or_loop(&grid, guard);
+    Some(route.split_off(cycle_start?))
+}
+
+// draws the states on the map the way the puzzle does: `|` and `-` for the walking direction,
+// `+` where the guard turns or crosses its own path, the guard symbol for the start and `O` for the obstruction
+pub fn render_route(inp: &Matrix<char>, states: &[Guard], obstruction: Option<(usize, usize)>) -> String {
+    let (mut canvas, start) = prepare(inp);
+    for (i, guard) in states.iter().enumerate() {
+        let turned = states.get(i + 1).is_some_and(|next| next.pos == guard.pos);
+        let mark = if guard.dir.0 == 0 { '-' } else { '|' };
+        let cell = &mut canvas[guard.pos];
+        *cell = match *cell {
+            '.' if !turned => mark,
+            c if c == mark => mark,
+            _ => '+',
+        };
+    }
+    canvas[start.pos] = inp[start.pos];
+    if let Some(pos) = obstruction {
+        canvas[pos] = 'O';
+    }
+    canvas
+        .iter()
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatrolEnd {
+    Exited,
+    // the guard keeps repeating its route from this index on
+    Looped(usize),
+}
+
+// two guards that pass the same cell, with the tick each of them first got there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crossing {
+    pub pos: (usize, usize),
+    pub guards: (usize, usize),
+    pub ticks: (usize, usize),
+}
+
+// all guards take one step (a move or a turn) per tick and walk through each other,
+// so the route index of a state is the tick it happens at
+#[derive(Debug, Clone)]
+pub struct Patrol {
+    pub routes: Vec<Vec<Guard>>,
+    pub ends: Vec<PatrolEnd>,
+}
+
+pub fn patrol(inp: &Matrix<char>) -> Patrol {
+    let (grid, guards) = prepare_all(inp);
+    let (routes, ends) = guards
+        .into_iter()
+        .map(|guard| {
+            let (route, cycle_start) = route_until_exit_or_loop(&grid, guard);
+            (route, cycle_start.map_or(PatrolEnd::Exited, PatrolEnd::Looped))
+        })
+        .unzip();
+    Patrol { routes, ends }
+}
+
+impl Patrol {
+    // the state of a guard at a tick, a looping guard goes on around its cycle
+    pub fn state_at(&self, guard: usize, tick: usize) -> Option<Guard> {
+        let route = &self.routes[guard];
+        match self.ends[guard] {
+            _ if tick < route.len() => Some(route[tick]),
+            PatrolEnd::Exited => None,
+            PatrolEnd::Looped(start) => Some(route[start + (tick - start) % (route.len() - start)]),
+        }
+    }
 
-        let mut v = HashSet::new();
-        while let Some(c) = grid.get(g.next_pos()) {
-            if !v.insert(g) {
-                count += 1;
-                break;
+    // cells visited by more than one guard, ordered by position and guard
+    pub fn crossings(&self) -> Vec<Crossing> {
+        let mut first_ticks: HashMap<(usize, usize), Vec<(usize, usize)>> = HashMap::new();
+        for (g, route) in self.routes.iter().enumerate() {
+            for (tick, state) in route.iter().enumerate() {
+                let visits = first_ticks.entry(state.pos).or_default();
+                if visits.last().is_none_or(|&(last, _)| last != g) {
+                    visits.push((g, tick));
+                }
+            }
+        }
+        let mut crossings = Vec::new();
+        for (pos, visits) in first_ticks {
+            for (i, &(a, ta)) in visits.iter().enumerate() {
+                for &(b, tb) in &visits[i + 1..] {
+                    crossings.push(Crossing { pos, guards: (a, b), ticks: (ta, tb) });
+                }
             }
-            match c {
-                '.' => g.make_move(),
-                '#' => g.turn_right(),
-                _ => panic!("Invalid character"),
-                
-            };
         }
-        grid[(r, c)] = '.';
+        crossings.sort_by_key(|c| (c.pos, c.guards));
+        crossings
     }
 
-    count
+    // the first tick two guards stand on the same cell; earlier guards win ties
+    pub fn first_collision(&self) -> Option<(usize, Crossing)> {
+        let mut first: Option<(usize, usize, usize)> = None;
+        for b in 0..self.routes.len() {
+            for a in 0..b {
+                if let Some(tick) = self.pair_collision(a, b) {
+                    if first.is_none_or(|best| (tick, b, a) < best) {
+                        first = Some((tick, b, a));
+                    }
+                }
+            }
+        }
+        let (tick, b, a) = first?;
+        let pos = self.state_at(a, tick)?.pos;
+        Some((tick, Crossing { pos, guards: (a, b), ticks: (tick, tick) }))
+    }
+
+    // the ticks before both guards are on their cycles are checked one by one; after that two
+    // visits to a shared cell line up on ticks given by the chinese remainder theorem, so the
+    // search never has to run through the common period of the two cycles
+    fn pair_collision(&self, a: usize, b: usize) -> Option<usize> {
+        let settled = self.routes[a].len().max(self.routes[b].len());
+        for tick in 0..settled {
+            match (self.state_at(a, tick), self.state_at(b, tick)) {
+                (Some(sa), Some(sb)) if sa.pos == sb.pos => return Some(tick),
+                (Some(_), Some(_)) => {}
+                _ => return None,
+            }
+        }
+        let (PatrolEnd::Looped(start_a), PatrolEnd::Looped(start_b)) = (self.ends[a], self.ends[b]) else {
+            return None;
+        };
+        let (cycle_a, cycle_b) = (&self.routes[a][start_a..], &self.routes[b][start_b..]);
+        let mut visits: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (offset, state) in cycle_a.iter().enumerate() {
+            visits.entry(state.pos).or_default().push(start_a + offset);
+        }
+        let mut first: Option<usize> = None;
+        for (offset, state) in cycle_b.iter().enumerate() {
+            for &index_a in visits.get(&state.pos).into_iter().flatten() {
+                let tick = first_common_tick((index_a, cycle_a.len()), (start_b + offset, cycle_b.len()), settled);
+                if let Some(tick) = tick.filter(|&t| first.is_none_or(|f| t < f)) {
+                    first = Some(tick);
+                }
+            }
+        }
+        first
+    }
+}
+
+// the first tick from `from` on that is `x1` modulo `m1` and `x2` modulo `m2`,
+// `None` if there is none or it does not fit in a usize
+fn first_common_tick((x1, m1): (usize, usize), (x2, m2): (usize, usize), from: usize) -> Option<usize> {
+    let (x1, m1, x2, m2, from) = (x1 as i128, m1 as i128, x2 as i128, m2 as i128, from as i128);
+    let solution = m1.extended_gcd(&m2);
+    let g = solution.gcd;
+    if (x2 - x1) % g != 0 {
+        return None;
+    }
+    // m1 * k = x2 - x1 (mod m2), and solution.x is the inverse of m1 / g modulo m2 / g
+    let k = ((x2 - x1) / g * solution.x).rem_euclid(m2 / g);
+    let period = m1 / g * m2;
+    usize::try_from(from + (x1 + m1 * k - from).rem_euclid(period)).ok()
+}
+
+fn count_loops(inp: &Matrix<char>) -> usize {
+    loop_obstructions(inp).len()
 }
 
 #[aoc(day6, part2)]
@@ -133,5 +453,121 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(&read_inputs(EXAMPLE1)), 6);
     }
+
+    #[test]
+    fn test_first_entries_on_loop() {
+        let mut grid = read_inputs(".#...\n....#\n.^...\n#....\n...#.");
+        grid[(2, 1)] = '.';
+        let entries = first_entries(&grid, Guard { pos: (2, 1), dir: (-1, 0) });
+        let cells: Vec<_> = entries.iter().map(|&(pos, _)| pos).collect();
+        assert_eq!(cells, vec![(1, 1), (1, 2), (1, 3), (2, 3), (3, 3), (3, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_route() {
+        let grid = read_inputs(EXAMPLE1);
+        let route = guard_route(&grid);
+        assert_eq!(route[0], Guard { pos: (6, 4), dir: (-1, 0) });
+        assert_eq!(route[5], Guard { pos: (1, 4), dir: (-1, 0) });
+        assert_eq!(route[6], Guard { pos: (1, 4), dir: (0, 1) });
+        assert_eq!(route.last().unwrap().pos, (9, 7));
+    }
+
+    #[test]
+    fn test_loop_obstructions() {
+        let grid = read_inputs(EXAMPLE1);
+        assert_eq!(
+            loop_obstructions(&grid),
+            vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]
+        );
+        assert!(loop_cycle(&grid, (0, 0)).is_none());
+        let cycle = loop_cycle(&grid, (6, 3)).unwrap();
+        assert_eq!(
+            render_route(&grid, &cycle, Some((6, 3))),
+            "....#.....\n\
+             ....+---+#\n\
+             ....|...|.\n\
+             ..#.|...|.\n\
+             ....|..#|.\n\
+             ....|...|.\n\
+             .#.O^---+.\n\
+             ........#.\n\
+             #.........\n\
+             ......#..."
+        );
+    }
+
+    #[test]
+    fn test_guards() {
+        let grid = read_inputs(".....\n..v..\n.....\n>....\n.....");
+        assert_eq!(find_guards(&grid), vec![
+            Guard { pos: (1, 2), dir: (1, 0) },
+            Guard { pos: (3, 0), dir: (0, 1) },
+        ]);
+        let patrol = patrol(&grid);
+        assert_eq!(patrol.ends, vec![PatrolEnd::Exited; 2]);
+        assert_eq!(patrol.routes.iter().map(|r| r.len()).collect::<Vec<_>>(), vec![4, 5]);
+        let crossing = Crossing { pos: (3, 2), guards: (0, 1), ticks: (2, 2) };
+        assert_eq!(patrol.crossings(), vec![crossing]);
+        assert_eq!(patrol.first_collision(), Some((2, crossing)));
+    }
+
+    #[test]
+    fn test_looping_guard() {
+        let grid = read_inputs("..v...\n.#....\n.^..#.\n#.....\n...#..\n......");
+        let patrol = patrol(&grid);
+        assert_eq!(patrol.ends, vec![PatrolEnd::Exited, PatrolEnd::Looped(0)]);
+        assert_eq!(patrol.state_at(1, 8), Some(Guard { pos: (3, 1), dir: (0, -1) }));
+        assert_eq!(patrol.state_at(1, 100), patrol.state_at(1, 100 % patrol.routes[1].len()));
+        assert_eq!(patrol.state_at(0, 100), None);
+        assert_eq!(patrol.crossings(), vec![
+            Crossing { pos: (2, 2), guards: (0, 1), ticks: (2, 2) },
+            Crossing { pos: (3, 2), guards: (0, 1), ticks: (3, 7) },
+        ]);
+        assert_eq!(patrol.first_collision().map(|(tick, c)| (tick, c.pos)), Some((2, (2, 2))));
+    }
+
+    // a guard walking along a row on a cycle of `len` states, only passing `shared` at `offset`
+    fn cycle_route(row: usize, len: usize, offset: usize, shared: (usize, usize)) -> Vec<Guard> {
+        (0..len)
+            .map(|i| Guard { pos: if i == offset { shared } else { (row, i) }, dir: (0, 1) })
+            .collect()
+    }
+
+    #[test]
+    fn test_collision_on_cycles() {
+        let shared = (9, 9);
+        // brute force over one common period for small cycles
+        for (len_a, len_b, offset_a, offset_b) in [(5, 7, 1, 3), (4, 6, 0, 3), (4, 6, 1, 2), (6, 6, 2, 2)] {
+            let patrol = Patrol {
+                routes: vec![cycle_route(0, len_a, offset_a, shared), cycle_route(1, len_b, offset_b, shared)],
+                ends: vec![PatrolEnd::Looped(0), PatrolEnd::Looped(0)],
+            };
+            let expected = (0..len_a * len_b).find(|&t| patrol.state_at(0, t).map(|s| s.pos) == patrol.state_at(1, t).map(|s| s.pos));
+            assert_eq!(patrol.first_collision().map(|(tick, _)| tick), expected);
+        }
+        // coprime cycles thousands of states long only meet after tens of millions of ticks
+        let patrol = Patrol {
+            routes: vec![cycle_route(0, 9973, 10, shared), cycle_route(1, 9967, 20, shared)],
+            ends: vec![PatrolEnd::Looped(0), PatrolEnd::Looped(0)],
+        };
+        let (tick, crossing) = patrol.first_collision().expect("coprime cycles meet");
+        assert_eq!((tick % 9973, tick % 9967), (10, 20));
+        assert!(tick > 9973 * 20);
+        assert_eq!(crossing.pos, shared);
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let mut grid = read_inputs(EXAMPLE1);
+        grid[(6, 4)] = '.';
+        let jumps = JumpTable::new(&grid);
+        assert_eq!(jumps.stop((6, 4), 0, (0, 0)), Some((1, 4)));
+        assert_eq!(jumps.stop((1, 4), 1, (0, 0)), Some((1, 8)));
+        assert_eq!(jumps.stop((1, 4), 1, (1, 6)), Some((1, 5)));
+        assert_eq!(jumps.stop((1, 4), 1, (1, 9)), Some((1, 8)));
+        assert_eq!(jumps.stop((7, 7), 2, (0, 0)), None);
+        assert_eq!(jumps.stop((7, 7), 2, (9, 7)), Some((8, 7)));
+    }
 }
 
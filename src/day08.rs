@@ -1,25 +1,18 @@
 use std::collections::{HashMap, HashSet};
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use num::integer::gcd;
 use pathfinding::prelude::Matrix;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-struct Point {
-    x: usize,
-    y: usize,
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Point {
+    pub x: usize,
+    pub y: usize,
 }
 
-struct Input {
-    points: HashMap<char, Vec<Point>>,
-    grid: Matrix<char>,
-}
-
-fn gcd(a: i32, b: i32) -> i32 {
-    if b == 0 {
-        a.abs()
-    } else {
-        gcd(b, a % b)
-    }
+pub struct Input {
+    pub points: HashMap<char, Vec<Point>>,
+    pub grid: Matrix<char>,
 }
 
 #[aoc_generator(day08)]
@@ -41,102 +34,85 @@ fn read_inputs(input: &str) -> Input {
     }
 }
 
-fn get_antinodes(p1: &Point, p2: &Point, max_y: usize, max_x: usize) -> Vec<(usize, usize)> {
-    let dx = p2.x as i32 - p1.x as i32;
-    let dy = p2.y as i32 - p1.y as i32;
-
-    let mut antinodes = vec![];
-    for &t in &[-1, 2] {
-        let px: i32 = p1.x as i32 + t * dx;
-        let py = p1.y as i32 + t * dy;
-        if px >= 0 && px < max_x as i32 && py >= 0 && py < max_y as i32 {
-            antinodes.push((py as usize, px as usize));
-        }
-        
-    }
-    antinodes
+// which points on the line through two antennas of one frequency are antinodes; `d` is the
+// offset from the first antenna to the second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Harmonics {
+    // only the two points k·d beyond either antenna: 1 is the first part, 0 the antennas themselves
+    Nth(u32),
+    // every grid point on the line at most this far (euclidean) from the nearer antenna
+    Within(u32),
+    // every grid point on the line, the second part
+    All,
 }
 
-fn get_antinodes_harmonics(points: &Vec<Point>, max_y: usize, max_x: usize) -> Vec<Point> {
-    let mut unique_antinodes = HashSet::new();
-
-    for i in 0..points.len() {
-        for j in i+1..points.len() {
-            let pair_antinodes = get_line_points_in_bounds(&points[i], &points[j], max_x, max_y);
-            for p in pair_antinodes {
-                unique_antinodes.insert(p);
+impl Harmonics {
+    // the line is walked in the smallest grid step `d / g`, so the antennas sit at steps 0 and `g`
+    fn includes(self, s: i64, g: i64, step_len2: i64) -> bool {
+        match self {
+            Harmonics::Nth(k) => s == -(k as i64) * g || s == g + k as i64 * g,
+            Harmonics::Within(dist) => {
+                let nearest = s.abs().min((s - g).abs());
+                nearest * nearest * step_len2 <= dist as i64 * dist as i64
             }
-            unique_antinodes.insert(points[i]);
-            unique_antinodes.insert(points[j]);
+            Harmonics::All => true,
         }
     }
+}
 
-    unique_antinodes.into_iter().collect()
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Antinodes {
+    pub points: HashSet<Point>,
+    pub by_frequency: HashMap<char, HashSet<Point>>,
 }
 
-fn get_line_points_in_bounds(p1: &Point, p2: &Point, max_x: usize, max_y: usize) -> Vec<Point> {
-    let dx = p2.x as i32 - p1.x as i32;
-    let dy = p2.y as i32 - p1.y as i32;
+// the antinodes of two antennas inside a `columns` x `rows` grid, walking the exact grid points
+// of their line outwards from the first antenna until the grid ends
+fn pair_antinodes(p1: Point, p2: Point, columns: usize, rows: usize, harmonics: Harmonics, found: &mut HashSet<Point>) {
+    let (dx, dy) = (p2.x as i64 - p1.x as i64, p2.y as i64 - p1.y as i64);
     let g = gcd(dx, dy);
-    let dxr = dx / g;
-    let dyr = dy / g;
-
-    let mut points = vec![];
-    let mut x = p1.x as i32;
-    let mut y = p1.y as i32;
-    loop {
-        x += dxr;
-        y += dyr;
-        if x < 0 || x >= max_x as i32 || y < 0 || y >= max_y as i32 {
-            break;
-        }
-        points.push(Point {x: x as usize, y: y as usize});
+    if g == 0 {
+        return;
     }
-    x = p1.x as i32;
-    y = p1.y as i32;
-    loop {
-        x -= dxr;
-        y -= dyr;
-        if x < 0 || x >= max_x as i32 || y < 0 || y >= max_y as i32 {
-            break;
+    let (sx, sy) = (dx / g, dy / g);
+    let at = |s: i64| {
+        let (x, y) = (p1.x as i64 + s * sx, p1.y as i64 + s * sy);
+        (x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64).then_some(Point { x: x as usize, y: y as usize })
+    };
+    for dir in [1, -1] {
+        let mut s = if dir == 1 { 0 } else { -1 };
+        while let Some(p) = at(s) {
+            if harmonics.includes(s, g, sx * sx + sy * sy) {
+                found.insert(p);
+            }
+            s += dir;
         }
-        points.push(Point {x: x as usize, y: y as usize});
     }
-
-    points
 }
 
-#[aoc(day08, part1)]
-fn part1(input: &Input) -> usize {
-    let max_x = input.grid.columns;
-    let max_y = input.grid.rows;
-    let mut unique_antinodes = HashSet::new();
-    for (_k, v) in &input.points {
-        for i in 0..v.len() {
-            for j in i+1..v.len() {
-                let pair_antinodes = get_antinodes(&v[i], &v[j], max_y, max_x);
-                for antinode in pair_antinodes {
-                    unique_antinodes.insert(antinode);
-                }
+pub fn find_antinodes(groups: &HashMap<char, Vec<Point>>, columns: usize, rows: usize, harmonics: Harmonics) -> Antinodes {
+    let mut antinodes = Antinodes::default();
+    for (&frequency, antennas) in groups {
+        let mut found = HashSet::new();
+        for i in 0..antennas.len() {
+            for j in i+1..antennas.len() {
+                pair_antinodes(antennas[i], antennas[j], columns, rows, harmonics, &mut found);
             }
         }
+        antinodes.points.extend(found.iter().copied());
+        antinodes.by_frequency.insert(frequency, found);
     }
-    unique_antinodes.len()
+    antinodes
+}
+
+#[aoc(day08, part1)]
+fn part1(input: &Input) -> usize {
+    find_antinodes(&input.points, input.grid.columns, input.grid.rows, Harmonics::Nth(1)).points.len()
 }
 
 #[aoc(day08, part2)]
 fn part2(input: &Input) -> usize {
-    let max_x = input.grid.columns;
-    let max_y = input.grid.rows;
-    let mut unique_antinodes = HashMap::new();
-    for (_k, v) in &input.points {
-        let antinodes = get_antinodes_harmonics(v, max_y, max_x);
-        for p in antinodes {
-            let entry = unique_antinodes.entry(p).or_insert(0);
-            *entry += 1;
-        }
-    }
-    unique_antinodes.len()
+    find_antinodes(&input.points, input.grid.columns, input.grid.rows, Harmonics::All).points.len()
 }
 
 
@@ -174,6 +150,41 @@ mod tests {
         let input = read_inputs(TEST_INPUT);
         assert_eq!(part2(&input), 34);
     }
-}
 
+    #[test]
+    fn test_harmonics() {
+        let input = read_inputs(TEST_INPUT);
+        let (columns, rows) = (input.grid.columns, input.grid.rows);
+        let antinodes = find_antinodes(&input.points, columns, rows, Harmonics::Nth(0));
+        assert_eq!(antinodes.points.len(), 7);
+        let antinodes = find_antinodes(&input.points, columns, rows, Harmonics::Nth(1));
+        assert_eq!(antinodes.by_frequency[&'A'].len(), 5);
+        assert!(antinodes.by_frequency[&'A'].contains(&Point { x: 10, y: 11 }));
+        // a harmonic beyond the grid is dropped
+        let antinodes = find_antinodes(&input.points, columns, rows, Harmonics::Nth(20));
+        assert!(antinodes.points.is_empty());
+
+        let all = find_antinodes(&input.points, columns, rows, Harmonics::All);
+        let near = find_antinodes(&input.points, columns, rows, Harmonics::Within(100));
+        assert_eq!(near, all);
+        let near = find_antinodes(&input.points, columns, rows, Harmonics::Within(0));
+        assert_eq!(near.points.len(), 7);
+    }
 
+    #[test]
+    fn test_reduced_step() {
+        // antennas two steps apart on a diagonal also have the grid point between them on their line
+        let groups = HashMap::from([('a', vec![Point { x: 0, y: 0 }, Point { x: 2, y: 2 }])]);
+        let all = find_antinodes(&groups, 5, 4, Harmonics::All);
+        let mut points = all.points.into_iter().collect::<Vec<_>>();
+        points.sort();
+        assert_eq!(points, (0..4).map(|i| Point { x: i, y: i }).collect::<Vec<_>>());
+        let first = find_antinodes(&groups, 5, 5, Harmonics::Nth(1));
+        assert_eq!(first.points, HashSet::from([Point { x: 4, y: 4 }]));
+        // one grid step from an antenna is sqrt(2) away
+        let near = find_antinodes(&groups, 5, 5, Harmonics::Within(1));
+        assert_eq!(near.points.len(), 2);
+        let near = find_antinodes(&groups, 5, 5, Harmonics::Within(2));
+        assert_eq!(near.points.len(), 4);
+    }
+}
@@ -1,73 +1,191 @@
-use std::collections::HashSet;
-
 use aoc_runner_derive::{aoc, aoc_generator};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-struct Point (usize, usize);
+pub struct Point (pub usize, pub usize);
 
-struct Input{
-    geomap: Vec<Vec<u32>>,
-    zero_coords: Vec<Point>,
+// `.` cells have no height and can not be walked on; trails climb from 0 to `max_height`
+pub struct Input{
+    pub geomap: Vec<Vec<Option<u32>>>,
+    pub zero_coords: Vec<Point>,
+    pub max_height: u32,
 }
 
 #[aoc_generator(day10)]
-fn read_inputs(inp: &str) -> Input{
-    let mut geomap: Vec<Vec<u32>> = vec![];
+pub fn read_inputs(inp: &str) -> Option<Input> {
+    let mut geomap: Vec<Vec<Option<u32>>> = vec![];
     let mut zero_coords: Vec<Point> = vec![];
 
     for (y, line) in inp.lines().enumerate() {
-        let mut row: Vec<u32> = vec![];
+        let mut row: Vec<Option<u32>> = vec![];
         for (x, c) in line.chars().enumerate() {
-            let c = c.to_digit(10).unwrap();
+            let c = match c {
+                '.' => None,
+                c => Some(c.to_digit(10)?),
+            };
             row.push(c);
-            if c == 0 {
+            if c == Some(0) {
                 zero_coords.push(Point(x, y));
             }
         }
         geomap.push(row);
     }
-    Input { geomap, zero_coords }
+    Some(Input { geomap, zero_coords, max_height: 9 })
 }
 
-fn check_valid_path(p: &Vec<Point>, value: u32, geomap: &Vec<Vec<u32>>,) -> Vec<Point> {
-    if value == 9 {
-        return p.to_owned().to_vec();
-    }
-    else {
-        let mut valid_path: Vec<Point> = vec![];
-        for point in p{
-            let x = point.0;
-            let y = point.1;
-            if x > 0 && geomap[y][x-1] == value + 1 {
-                valid_path.push(Point(x-1, y));
-            }
-            if x < geomap[0].len() - 1 && geomap[y][x+1] == value + 1 {
-                valid_path.push(Point(x+1, y));
-            }
-            if y > 0 && geomap[y-1][x] == value + 1 {
-                valid_path.push(Point(x, y-1));
+fn neighbours(geomap: &[Vec<Option<u32>>], Point(x, y): Point) -> impl Iterator<Item = Point> + '_ {
+    let candidates = [
+        x.checked_sub(1).map(|x| Point(x, y)),
+        Some(Point(x + 1, y)),
+        y.checked_sub(1).map(|y| Point(x, y)),
+        Some(Point(x, y + 1)),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .filter(|p| geomap.get(p.1).and_then(|row| row.get(p.0)).is_some())
+}
+
+fn height_at(geomap: &[Vec<Option<u32>>], p: Point) -> Option<u32> {
+    geomap.get(p.1)?.get(p.0).copied().flatten()
+}
+
+// for every cell the peaks reachable on a trail from it (a bitset over `peaks`) and the number
+// of distinct trails to any peak; filled from the peaks down one height at a time, so each
+// cell only looks at its neighbours one step higher
+pub struct TrailAnalysis {
+    pub peaks: Vec<Point>,
+    width: usize,
+    reachable: Vec<Vec<u64>>,
+    ratings: Vec<u64>,
+}
+
+impl TrailAnalysis {
+    pub fn new(inp: &Input) -> TrailAnalysis {
+        let geomap = &inp.geomap;
+        let width = geomap.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut by_height = vec![Vec::new(); inp.max_height as usize + 1];
+        for (y, row) in geomap.iter().enumerate() {
+            for (x, &h) in row.iter().enumerate() {
+                if let Some(h) = h.filter(|&h| h <= inp.max_height) {
+                    by_height[h as usize].push(Point(x, y));
+                }
             }
-            if y < geomap.len() - 1 && geomap[y+1][x] == value + 1 {
-                valid_path.push(Point(x, y+1));
+        }
+
+        let peaks = by_height[inp.max_height as usize].clone();
+        let words = peaks.len().div_ceil(64);
+        // `.` cells and cells above the peaks keep an empty set and no trails
+        let mut reachable = vec![vec![0; words]; width * geomap.len()];
+        let mut ratings = vec![0; width * geomap.len()];
+        for (i, p) in peaks.iter().enumerate() {
+            let cell = p.1 * width + p.0;
+            reachable[cell][i / 64] |= 1 << (i % 64);
+            ratings[cell] = 1;
+        }
+        for h in (0..inp.max_height).rev() {
+            for &p in &by_height[h as usize] {
+                let mut peaks_here = vec![0; words];
+                let mut rating = 0;
+                for n in neighbours(geomap, p).filter(|&n| height_at(geomap, n) == Some(h + 1)) {
+                    let cell = n.1 * width + n.0;
+                    rating += ratings[cell];
+                    for (a, b) in peaks_here.iter_mut().zip(&reachable[cell]) {
+                        *a |= b;
+                    }
+                }
+                reachable[p.1 * width + p.0] = peaks_here;
+                ratings[p.1 * width + p.0] = rating;
             }
-       }
-         return check_valid_path(&valid_path, value + 1, geomap);
+        }
+        TrailAnalysis { peaks, width, reachable, ratings }
+    }
+
+    // the peaks a trail starting at `p` can reach
+    pub fn reachable_peaks(&self, p: Point) -> Vec<Point> {
+        let bits = &self.reachable[p.1 * self.width + p.0];
+        (0..self.peaks.len()).filter(|&i| bits[i / 64] >> (i % 64) & 1 == 1).map(|i| self.peaks[i]).collect()
+    }
+
+    pub fn score(&self, p: Point) -> usize {
+        self.reachable[p.1 * self.width + p.0].iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn rating(&self, p: Point) -> u64 {
+        self.ratings[p.1 * self.width + p.0]
+    }
+}
+
+// every trail from `start` as the cells it visits, from height 0 to the peak; cells that can not
+// reach a peak are never entered, so the work is proportional to the trails found
+pub fn trails_from(inp: &Input, start: Point) -> Vec<Vec<Point>> {
+    let analysis = TrailAnalysis::new(inp);
+    let mut trails = Vec::new();
+    if height_at(&inp.geomap, start) != Some(0) || analysis.rating(start) == 0 {
+        return trails;
+    }
+    let mut path = vec![start];
+    extend_trails(inp, &analysis, &mut path, &mut trails);
+    trails
+}
+
+fn extend_trails(inp: &Input, analysis: &TrailAnalysis, path: &mut Vec<Point>, trails: &mut Vec<Vec<Point>>) {
+    let p = path[path.len() - 1];
+    let h = height_at(&inp.geomap, p).unwrap_or(0);
+    if h == inp.max_height {
+        trails.push(path.clone());
+        return;
+    }
+    for n in neighbours(&inp.geomap, p) {
+        if height_at(&inp.geomap, n) == Some(h + 1) && analysis.rating(n) > 0 {
+            path.push(n);
+            extend_trails(inp, analysis, path, trails);
+            path.pop();
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailView {
+    // the whole topographic map, trail cells in bold green and the rest dimmed
+    Overlay,
+    // only the cells on some trail show their height, everything else is `.`
+    TrailsOnly,
+}
+
+pub fn render_trails(inp: &Input, trails: &[Vec<Point>], view: TrailView) -> String {
+    let mut on_trail = inp.geomap.iter().map(|row| vec![false; row.len()]).collect::<Vec<_>>();
+    for &p in trails.iter().flatten() {
+        if let Some(cell) = on_trail.get_mut(p.1).and_then(|row| row.get_mut(p.0)) {
+            *cell = true;
+        }
+    }
+    let mut out = String::new();
+    for (y, row) in inp.geomap.iter().enumerate() {
+        if y > 0 {
+            out.push('\n');
+        }
+        for (x, &h) in row.iter().enumerate() {
+            let c = h.map_or('.', |h| char::from_digit(h, 10).unwrap_or('?'));
+            match (view, h.is_some() && on_trail[y][x]) {
+                (TrailView::Overlay, true) => out.push_str(&format!("\x1b[1;32m{}\x1b[0m", c)),
+                (TrailView::Overlay, false) if h.is_some() => out.push_str(&format!("\x1b[90m{}\x1b[0m", c)),
+                (TrailView::Overlay, false) => out.push(c),
+                (TrailView::TrailsOnly, true) => out.push(c),
+                (TrailView::TrailsOnly, false) => out.push('.'),
+            }
+        }
+    }
+    out
+}
 
-fn calculate_score_and_rating(inp: &Input) -> (usize, usize){
-    let geo_map = &inp.geomap;
-    let zero_coords = &inp.zero_coords;
+fn calculate_score_and_rating(inp: &Input) -> (usize, u64){
+    let analysis = TrailAnalysis::new(inp);
 
     let mut total_score = 0;
     let mut total_rating = 0;
-    for &start in zero_coords {
-        let endpoints = check_valid_path(&vec![start], 0, &geo_map);
-        total_rating += endpoints.len();
-        let unique_endpoints: HashSet<Point> = endpoints.into_iter().collect();
-        total_score += unique_endpoints.len();
-        
+    for &start in &inp.zero_coords {
+        total_score += analysis.score(start);
+        total_rating += analysis.rating(start);
     }
 
     (total_score, total_rating)
@@ -79,7 +197,7 @@ fn part1(inp: &Input) -> usize {
 }
 
 #[aoc(day10, part2)]
-fn part2(inp: &Input) -> usize {
+fn part2(inp: &Input) -> u64 {
     calculate_score_and_rating(inp).1
 }
 
@@ -98,15 +216,72 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let inp = read_inputs(TEST_INPUT);
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
         let res = part1(&inp);
         assert_eq!(res, 36);
     }
 
     #[test]
     fn test_part2() {
-        let inp = read_inputs(TEST_INPUT);
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
         let res = part2(&inp);
         assert_eq!(res, 81);
     }
+
+    #[test]
+    fn test_impassable_cells() {
+        let inp = read_inputs("...0...\n...1...\n...2...\n6543456\n7.....7\n8.....8\n9.....9").expect("valid input");
+        assert_eq!(calculate_score_and_rating(&inp).0, 2);
+        let inp = read_inputs("..90..9\n...1.98\n...2..7\n6543456\n765.987\n876....\n987....").expect("valid input");
+        assert_eq!(calculate_score_and_rating(&inp).0, 4);
+        let inp = read_inputs(".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....").expect("valid input");
+        assert_eq!(calculate_score_and_rating(&inp).1, 3);
+        let inp = read_inputs("012345\n123456\n234567\n345678\n4.6789\n56789.").expect("valid input");
+        assert_eq!(calculate_score_and_rating(&inp).1, 227);
+        assert!(read_inputs("01x").is_none());
+    }
+
+    #[test]
+    fn test_analysis() {
+        let mut inp = read_inputs("0123\n1.34\n2345").expect("valid input");
+        let analysis = TrailAnalysis::new(&inp);
+        assert_eq!(analysis.score(Point(0, 0)), 0);
+        inp.max_height = 4;
+        let analysis = TrailAnalysis::new(&inp);
+        assert_eq!(analysis.reachable_peaks(Point(0, 0)), vec![Point(3, 1), Point(2, 2)]);
+        assert_eq!(analysis.rating(Point(0, 0)), 4);
+        assert_eq!(analysis.rating(Point(2, 0)), 3);
+        assert_eq!(analysis.score(Point(1, 1)), 0);
+        assert_eq!(analysis.reachable_peaks(Point(1, 1)), vec![]);
+        assert_eq!(analysis.rating(Point(1, 1)), 0);
+        // the 5 is above the peaks
+        assert_eq!(analysis.reachable_peaks(Point(3, 2)), vec![]);
+    }
+
+    #[test]
+    fn test_trails() {
+        let inp = read_inputs(".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....").expect("valid input");
+        let trails = trails_from(&inp, Point(5, 0));
+        assert_eq!(trails.len(), 3);
+        assert!(trails.iter().all(|t| t.len() == 10 && t[9] == Point(2, 6)));
+        assert_eq!(render_trails(&inp, &trails[..1], TrailView::TrailsOnly), ".....0.\n..4321.\n..5....\n..6....\n..7....\n..8....\n..9....");
+        assert_eq!(render_trails(&inp, &trails, TrailView::TrailsOnly), ".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....");
+
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
+        let trails = trails_from(&inp, Point(2, 0));
+        let overlay = render_trails(&inp, &trails, TrailView::Overlay);
+        // the colour codes aside it is the whole map
+        let plain = overlay.replace("\x1b[1;32m", "").replace("\x1b[90m", "").replace("\x1b[0m", "");
+        assert_eq!(plain, TEST_INPUT);
+        // 8 is off the trails, the 9 next to it ends one that starts at the 0
+        assert!(overlay.starts_with("\x1b[90m8\x1b[0m\x1b[1;32m9\x1b[0m\x1b[1;32m0\x1b[0m"));
+        let highlighted = trails.iter().flatten().collect::<std::collections::HashSet<_>>().len();
+        assert_eq!(overlay.matches("\x1b[1;32m").count(), highlighted);
+
+        let analysis = TrailAnalysis::new(&inp);
+        for &start in &inp.zero_coords {
+            assert_eq!(trails_from(&inp, start).len() as u64, analysis.rating(start));
+        }
+        assert!(trails_from(&inp, Point(0, 0)).is_empty());
+    }
 }
@@ -1,36 +1,124 @@
+use std::collections::HashMap;
+
 use aoc_runner_derive::{aoc, aoc_generator};
-use memoize::memoize;
 
 #[aoc_generator(day11)]
 fn read_inputs(input: &str) -> Vec<i128> {
     input.split_whitespace().map(|x| x.parse().unwrap()).collect()
 }
 
-#[memoize]
-fn get_span(stone: i128, num_blinks: i128) -> i128 {
-    if num_blinks == 0 {
-        return 1;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    // a stone engraved with `from` becomes the stones in `to`
+    Replace { from: i128, to: Vec<i128> },
+    // a stone with an even number of digits in `base` splits into its two halves;
+    // `base` has to be at least 2
+    SplitEvenDigits { base: i128 },
+    // any stone is multiplied by the factor; a product beyond i128 panics, in release builds too
+    Multiply(i128),
+}
+
+impl Rule {
+    pub fn apply(&self, stone: i128) -> Option<Vec<i128>> {
+        match self {
+            Rule::Replace { from, to } => (stone == *from).then(|| to.clone()),
+            Rule::SplitEvenDigits { base } => {
+                let n = count_digits(stone, *base);
+                if !n.is_multiple_of(2) {
+                    return None;
+                }
+                let half = base.pow(n / 2);
+                Some(vec![stone / half, stone % half])
+            }
+            Rule::Multiply(factor) => {
+                let product = stone
+                    .checked_mul(*factor)
+                    .unwrap_or_else(|| panic!("stone {} times {} does not fit in an i128", stone, factor));
+                Some(vec![product])
+            }
+        }
+    }
+}
+
+fn count_digits(mut stone: i128, base: i128) -> u32 {
+    assert!(base >= 2, "digits need a base of at least 2, got {}", base);
+    let mut n = 1;
+    while stone >= base {
+        stone /= base;
+        n += 1;
+    }
+    n
+}
+
+// rules are tried in order, the first one that matches wins;
+// a stone no rule matches stays unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    // panics if `split_base` is below 2, there are no digits to split in such a base
+    pub fn new(multiplier: i128, split_base: i128) -> RuleSet {
+        assert!(split_base >= 2, "split base has to be at least 2, got {}", split_base);
+        RuleSet {
+            rules: vec![
+                Rule::Replace { from: 0, to: vec![1] },
+                Rule::SplitEvenDigits { base: split_base },
+                Rule::Multiply(multiplier),
+            ],
+        }
+    }
+
+    // extra rules take priority over the ones already in the set
+    pub fn with_rule(mut self, rule: Rule) -> RuleSet {
+        self.rules.insert(0, rule);
+        self
+    }
+
+    pub fn blink_stone(&self, stone: i128) -> Vec<i128> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(stone))
+            .unwrap_or_else(|| vec![stone])
+    }
+
+    pub fn blink(&self, stones: &HashMap<i128, i128>) -> HashMap<i128, i128> {
+        let mut next = HashMap::new();
+        for (&stone, &count) in stones {
+            for new_stone in self.blink_stone(stone) {
+                *next.entry(new_stone).or_insert(0) += count;
+            }
+        }
+        next
+    }
+
+    pub fn count_stones(&self, stones: &[i128], num_blinks: usize) -> i128 {
+        let mut counts = HashMap::new();
+        for &stone in stones {
+            *counts.entry(stone).or_insert(0) += 1;
+        }
+        for _ in 0..num_blinks {
+            counts = self.blink(&counts);
+        }
+        counts.values().sum()
     }
-    if stone == 0 {
-        return get_span(1, num_blinks - 1);
-    } else if stone.to_string().len() % 2 == 0 {
-        let n = stone.to_string().len();
-        let left = stone.to_string()[..n / 2].parse().unwrap();
-        let right= stone.to_string()[n / 2..].parse().unwrap();
-        return get_span(left, num_blinks - 1) + get_span(right, num_blinks - 1);
-    } else {
-        return get_span(2024 * stone, num_blinks - 1);
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet::new(2024, 10)
     }
 }
 
 #[aoc(day11, part1)]
-fn part1(stones: &Vec<i128>) -> i128 {
-    stones.iter().map(|&stone| get_span(stone, 25)).sum()
+fn part1(stones: &[i128]) -> i128 {
+    RuleSet::default().count_stones(stones, 25)
 }
 
 #[aoc(day11, part2)]
-fn part2(stones: &Vec<i128>) -> i128 {
-    stones.iter().map(|&stone| get_span(stone, 75)).sum()
+fn part2(stones: &[i128]) -> i128 {
+    RuleSet::default().count_stones(stones, 75)
 }
 
 #[cfg(test)]
@@ -45,4 +133,39 @@ mod tests {
         assert_eq!(part1(&inpt), 55312);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_blink_stone() {
+        let rules = RuleSet::default();
+        assert_eq!(rules.blink_stone(0), vec![1]);
+        assert_eq!(rules.blink_stone(1000), vec![10, 0]);
+        assert_eq!(rules.blink_stone(125), vec![253000]);
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        // 5 = 0b101 has three binary digits, 10 = 0b1010 splits into 0b10 and 0b10
+        let rules = RuleSet::new(3, 2).with_rule(Rule::Replace { from: 7, to: vec![7, 7] });
+        assert_eq!(rules.blink_stone(5), vec![15]);
+        assert_eq!(rules.blink_stone(10), vec![2, 2]);
+        assert_eq!(rules.blink_stone(7), vec![7, 7]);
+        assert_eq!(rules.count_stones(&[7], 3), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "split base has to be at least 2")]
+    fn test_invalid_split_base() {
+        RuleSet::new(2024, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "digits need a base of at least 2")]
+    fn test_invalid_split_rule() {
+        RuleSet::default().with_rule(Rule::SplitEvenDigits { base: 0 }).blink_stone(12);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit in an i128")]
+    fn test_multiply_overflow() {
+        RuleSet::new(i128::MAX, 10).count_stones(&[3], 2);
+    }
+}
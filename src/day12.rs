@@ -1,293 +1,326 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, fmt::Write};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-struct Region {
-    _plant_type: char,
-    plots: HashMap<(usize, usize), Plot>,
-    sides: usize,
-    visited_sides: HashSet<(usize, usize, Direction)>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionStats {
+    pub id: usize,
+    pub plant_type: char,
+    pub area: usize,
+    pub perimeter: usize,
+    pub sides: usize,
+    pub holes: usize,
 }
 
-struct Plot {
-    perimiters: usize,
+#[aoc_generator(day12)]
+fn parse_input(s: &str) -> Vec<Vec<char>> {
+    s.trim()
+        .lines()
+        .map(|line| line.chars().collect())
+        .collect()
 }
 
-#[derive(PartialEq, Default)]
-struct Sides {
-    up: bool,
-    down: bool,
-    left: bool,
-    right: bool,
+fn get_label(x: isize, y: isize, labels: &[Vec<usize>]) -> Option<usize> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    labels.get(y as usize)?.get(x as usize).copied()
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+// the region id of every plot; ids count up from 0 in reading order of each region's first plot
+pub fn label_regions(grid: &[Vec<char>]) -> Vec<Vec<usize>> {
+    let mut labels = grid.iter().map(|row| vec![usize::MAX; row.len()]).collect::<Vec<_>>();
+    let mut next_id = 0;
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &plant_type) in row.iter().enumerate() {
+            if labels[y][x] != usize::MAX {
+                continue;
+            }
+            labels[y][x] = next_id;
+            let mut stack = vec![(x, y)];
+            while let Some((x, y)) = stack.pop() {
+                let neighbours = [
+                    y.checked_sub(1).map(|y| (x, y)),
+                    Some((x, y + 1)),
+                    x.checked_sub(1).map(|x| (x, y)),
+                    Some((x + 1, y)),
+                ];
+                for (nx, ny) in neighbours.into_iter().flatten() {
+                    if grid.get(ny).and_then(|row| row.get(nx)) == Some(&plant_type) && labels[ny][nx] == usize::MAX {
+                        labels[ny][nx] = next_id;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            next_id += 1;
+        }
+    }
+    labels
 }
 
-fn rot_90(d: Direction) -> Direction {
-    match d {
-        Direction::Up => Direction::Right,
-        Direction::Down => Direction::Left,
-        Direction::Left => Direction::Up,
-        Direction::Right => Direction::Down,
+// one pass over the labelled plots: every plot adds its fence edges to the perimeter and every
+// corner of the region to the sides, as a polygon has as many sides as corners. Holes come from
+// the euler characteristic of the region's closed squares, vertices - edges + faces = 1 - holes
+pub fn region_stats(grid: &[Vec<char>]) -> Vec<RegionStats> {
+    let labels = label_regions(grid);
+    let mut stats: Vec<RegionStats> = Vec::new();
+    for (y, row) in labels.iter().enumerate() {
+        for (x, &id) in row.iter().enumerate() {
+            if id == stats.len() {
+                stats.push(RegionStats { id, plant_type: grid[y][x], area: 0, perimeter: 0, sides: 0, holes: 0 });
+            }
+            let same = |dx: isize, dy: isize| get_label(x as isize + dx, y as isize + dy, &labels) == Some(id);
+            let region = &mut stats[id];
+            region.area += 1;
+            region.perimeter += [(0, -1), (0, 1), (-1, 0), (1, 0)].iter().filter(|&&(dx, dy)| !same(dx, dy)).count();
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                let (side_x, side_y) = (same(dx, 0), same(0, dy));
+                if (!side_x && !side_y) || (side_x && side_y && !same(dx, dy)) {
+                    region.sides += 1;
+                }
+            }
+        }
     }
-}
 
-fn rot_270(d: Direction) -> Direction {
-    match d {
-        Direction::Up => Direction::Left,
-        Direction::Down => Direction::Right,
-        Direction::Left => Direction::Down,
-        Direction::Right => Direction::Up,
+    // distinct lattice points touched by each region; a point is shared by up to four plots
+    let mut vertices = vec![0usize; stats.len()];
+    let height = labels.len() as isize;
+    let width = labels.iter().map(|row| row.len()).max().unwrap_or(0) as isize;
+    for vy in 0..=height {
+        for vx in 0..=width {
+            let mut around = [(vx - 1, vy - 1), (vx, vy - 1), (vx - 1, vy), (vx, vy)]
+                .map(|(x, y)| get_label(x, y, &labels));
+            around.sort_unstable();
+            for (i, id) in around.iter().enumerate() {
+                if let Some(id) = *id {
+                    if i == 0 || around[i - 1] != Some(id) {
+                        vertices[id] += 1;
+                    }
+                }
+            }
+        }
+    }
+    for region in &mut stats {
+        // every plot has four edges, the ones between two plots of the region are shared
+        let edges = 2 * region.area + region.perimeter / 2;
+        region.holes = 1 + edges - vertices[region.id] - region.area;
     }
+    stats
 }
 
-fn get_plant_type(x: usize, y: usize, grid: &[Vec<char>]) -> Option<char> {
-    grid.get(y)?.get(x).copied()
+// a region's fence as rings of corner points in grid coordinates (y grows downwards). The outer
+// ring runs clockwise on screen and the holes counter-clockwise, so the region is always to the
+// right of the fence; read with y upwards that is the GeoJSON winding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polygon {
+    pub id: usize,
+    pub plant_type: char,
+    pub outer: Vec<(usize, usize)>,
+    pub holes: Vec<Vec<(usize, usize)>>,
 }
 
-#[aoc_generator(day12)]
-fn parse_input(s: &str) -> Vec<Vec<char>> {
-    s.trim()
-        .lines()
-        .map(|line| line.chars().collect())
-        .collect()
+impl Polygon {
+    // equals the number of sides of the region
+    pub fn vertex_count(&self) -> usize {
+        self.outer.len() + self.holes.iter().map(|h| h.len()).sum::<usize>()
+    }
+
+    fn rings(&self) -> impl Iterator<Item = &Vec<(usize, usize)>> {
+        std::iter::once(&self.outer).chain(self.holes.iter())
+    }
 }
 
-fn check_edges(x: usize, y: usize, plant_type: char, grid: &[Vec<char>]) -> Sides {
-    let mut sides = Sides::default();
-    if y.checked_sub(1)
-        .map(|y| (x, y))
-        .and_then(|n| get_plant_type(n.0, n.1, grid))
-        != Some(plant_type)
-    {
-        sides.up = true;
-    };
-    if Some((x, y + 1)).and_then(|n| get_plant_type(n.0, n.1, grid)) != Some(plant_type) {
-        sides.down = true;
-    };
-    if x.checked_sub(1)
-        .map(|x| (x, y))
-        .and_then(|n| get_plant_type(n.0, n.1, grid))
-        != Some(plant_type)
-    {
-        sides.left = true
-    };
-    if Some((x + 1, y)).and_then(|n| get_plant_type(n.0, n.1, grid)) != Some(plant_type) {
-        sides.right = true;
-    };
-    sides
+// a unit fence edge as its start point and direction
+type Edge = ((usize, usize), (isize, isize));
+
+fn edge_end(((x, y), (dx, dy)): Edge) -> (usize, usize) {
+    ((x as isize + dx) as usize, (y as isize + dy) as usize)
 }
 
-fn get_next_edge_position(
-    x: usize,
-    y: usize,
-    direction: Direction,
-    plant_type: char,
-    grid: &[Vec<char>],
-) -> (usize, usize, Direction) {
-    let get_offset = |x: usize, y: usize, x_offset, y_offset| {
-        y.checked_add_signed(y_offset).and_then(|y| {
-            x.checked_add_signed(x_offset)
-                .and_then(|x| get_plant_type(x, y, grid))
-        })
+// chains the fence edges into closed rings of corners. Where two plots of the region only touch
+// at a corner the ring turns left, around the outside plot, so the plots on the other side of
+// the corner get fences of their own: the same choice as the corner counting and the hole count
+fn trace_rings(edges: &[Edge]) -> Vec<Vec<(usize, usize)>> {
+    let mut outgoing: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (i, &(start, _)) in edges.iter().enumerate() {
+        outgoing.entry(start).or_default().push(i);
+    }
+    let turn_rank = |(dx, dy): (isize, isize), next: (isize, isize)| {
+        if next == (dy, -dx) {
+            0
+        } else if next == (dx, dy) {
+            1
+        } else {
+            2
+        }
     };
 
-    match direction {
-        Direction::Up => {
-            if get_offset(x, y, 0, -1) == Some(plant_type)
-                && get_offset(x, y, -1, -1) == Some(plant_type)
-            {
-                return (x - 1, y - 1, rot_270(direction));
-            }
-            if get_offset(x, y, 0, -1) != Some(plant_type) {
-                return (x, y, rot_90(direction));
-            }
-            (x, y - 1, direction)
-        }
-        Direction::Down => {
-            if get_offset(x, y, 0, 1) == Some(plant_type)
-                && get_offset(x, y, 1, 1) == Some(plant_type)
-            {
-                return (x + 1, y + 1, rot_270(direction));
-            }
-            if get_offset(x, y, 0, 1) != Some(plant_type) {
-                return (x, y, rot_90(direction));
-            }
-            (x, y + 1, direction)
+    let mut used = vec![false; edges.len()];
+    let mut rings = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
         }
-        Direction::Left => {
-            if get_offset(x, y, -1, 0) == Some(plant_type)
-                && get_offset(x, y, -1, 1) == Some(plant_type)
-            {
-                return (x - 1, y + 1, rot_270(direction));
-            }
-            if get_offset(x, y, -1, 0) != Some(plant_type) {
-                return (x, y, rot_90(direction));
+        let mut trace = Vec::new();
+        let mut current = first;
+        loop {
+            used[current] = true;
+            trace.push(edges[current]);
+            let next = outgoing[&edge_end(edges[current])]
+                .iter()
+                .copied()
+                .min_by_key(|&e| turn_rank(edges[current].1, edges[e].1));
+            match next {
+                Some(e) if e != first => current = e,
+                _ => break,
             }
-            (x - 1, y, direction)
         }
-        Direction::Right => {
-            if get_offset(x, y, 1, 0) == Some(plant_type)
-                && get_offset(x, y, 1, -1) == Some(plant_type)
-            {
-                return (x + 1, y - 1, rot_270(direction));
+        let corners = (0..trace.len())
+            .filter(|&i| trace[i].1 != trace[(i + trace.len() - 1) % trace.len()].1)
+            .map(|i| trace[i].0)
+            .collect();
+        rings.push(corners);
+    }
+    rings
+}
+
+// twice the signed area of a ring, positive for the clockwise (on screen) outer rings
+fn doubled_area(ring: &[(usize, usize)]) -> i64 {
+    (0..ring.len())
+        .map(|i| {
+            let (x1, y1) = ring[i];
+            let (x2, y2) = ring[(i + 1) % ring.len()];
+            x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64
+        })
+        .sum()
+}
+
+pub fn region_polygons(grid: &[Vec<char>]) -> Vec<Polygon> {
+    let labels = label_regions(grid);
+    let mut edges: Vec<Vec<Edge>> = Vec::new();
+    let mut plant_types = Vec::new();
+    for (y, row) in labels.iter().enumerate() {
+        for (x, &id) in row.iter().enumerate() {
+            if id == edges.len() {
+                edges.push(Vec::new());
+                plant_types.push(grid[y][x]);
             }
-            if get_offset(x, y, 1, 0) != Some(plant_type) {
-                return (x, y, rot_90(direction));
+            let same = |dx: isize, dy: isize| get_label(x as isize + dx, y as isize + dy, &labels) == Some(id);
+            let fences = [
+                ((0, -1), ((x, y), (1, 0))),
+                ((1, 0), ((x + 1, y), (0, 1))),
+                ((0, 1), ((x + 1, y + 1), (-1, 0))),
+                ((-1, 0), ((x, y + 1), (0, -1))),
+            ];
+            for ((dx, dy), edge) in fences {
+                if !same(dx, dy) {
+                    edges[id].push(edge);
+                }
             }
-            (x + 1, y, direction)
         }
     }
-}
 
-fn get_perimiters_and_unvisited_neighbours(
-    x: usize,
-    y: usize,
-    plant_type: char,
-    grid: &[Vec<char>],
-    visited: &HashSet<(usize, usize)>,
-) -> (usize, Vec<(usize, usize)>) {
-    let neighbours = [
-        y.checked_sub(1).map(|y| (x, y)),
-        Some((x, y + 1)),
-        x.checked_sub(1).map(|x| (x, y)),
-        Some((x + 1, y)),
-    ];
-    let this_perimiters = neighbours
+    edges
         .iter()
-        .filter(|n| n.and_then(|n| get_plant_type(n.0, n.1, grid)) != Some(plant_type))
-        .count();
-    let unvisited_neghbours = neighbours
-        .into_iter()
-        .flatten()
-        .filter(|n| !visited.contains(n))
-        .filter(|n| get_plant_type(n.0, n.1, grid) == Some(plant_type))
-        .collect();
-    (this_perimiters, unvisited_neghbours)
+        .enumerate()
+        .map(|(id, edges)| {
+            let (outer, holes): (Vec<_>, Vec<_>) = trace_rings(edges).into_iter().partition(|ring| doubled_area(ring) > 0);
+            Polygon { id, plant_type: plant_types[id], outer: outer.into_iter().next().unwrap_or_default(), holes }
+        })
+        .collect()
 }
 
-fn visit_location(
-    x: usize,
-    y: usize,
-    grid: &[Vec<char>],
-    visited: &mut HashSet<(usize, usize)>,
-) -> Option<char> {
-    let plot = get_plant_type(x, y, grid);
-    if !visited.insert((x, y)) {
-        return None;
-    };
-    plot
+fn json_char(c: char) -> String {
+    match c {
+        '"' | '\\' => format!("\\{}", c),
+        c => c.to_string(),
+    }
 }
 
-fn walk_region(
-    x: usize,
-    y: usize,
-    plant_type: char,
-    grid: &[Vec<char>],
-    visited: &mut HashSet<(usize, usize)>,
-    region: &mut Region,
-) {
-    if visit_location(x, y, grid, visited) != Some(plant_type) {
-        return;
-    }
-    let (perimiters, unvisited) =
-        get_perimiters_and_unvisited_neighbours(x, y, plant_type, grid, visited);
-    if check_edges(x, y, plant_type, grid).left
-        && !region.visited_sides.contains(&(x, y, Direction::Up))
-    {
-        let (sides, visited_sides) = walk_sides(x, y, plant_type, grid);
-        region.visited_sides.extend(visited_sides);
-        region.sides += sides;
-    }
-    let insert_result = region.plots.insert((x, y), Plot { perimiters });
-    debug_assert!(insert_result.is_none());
-    for (x, y) in unvisited {
-        walk_region(x, y, plant_type, grid, visited, region);
+fn xml_char(c: char) -> String {
+    match c {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        '"' => "&quot;".to_string(),
+        '\'' => "&apos;".to_string(),
+        c => c.to_string(),
     }
 }
 
-fn walk_sides(
-    mut x: usize,
-    mut y: usize,
-    plant_type: char,
-    grid: &[Vec<char>],
-) -> (usize, HashSet<(usize, usize, Direction)>) {
-    debug_assert!(check_edges(x, y, plant_type, grid).left);
-    let mut total_sides = 0;
-    let mut direction = Direction::Up;
-    let mut visited = HashSet::new();
-    loop {
-        if !visited.insert((x, y, direction)) {
-            break;
-        }
-        let prev_direction = direction;
-        (x, y, direction) = get_next_edge_position(x, y, direction, plant_type, grid);
-        if direction != prev_direction {
-            total_sides += 1
-        };
-    }
-    (total_sides, visited)
+// a FeatureCollection with one Polygon feature per region, rings closed by repeating the first corner
+pub fn to_geojson(polygons: &[Polygon]) -> String {
+    let features = polygons
+        .iter()
+        .map(|polygon| {
+            let rings = polygon
+                .rings()
+                .map(|ring| {
+                    let points = ring.iter().chain(ring.first()).map(|(x, y)| format!("[{},{}]", x, y)).collect::<Vec<_>>();
+                    format!("[{}]", points.join(","))
+                })
+                .collect::<Vec<_>>();
+            format!(
+                r#"{{"type":"Feature","properties":{{"id":{},"plant":"{}"}},"geometry":{{"type":"Polygon","coordinates":[{}]}}}}"#,
+                polygon.id,
+                json_char(polygon.plant_type),
+                rings.join(",")
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(r#"{{"type":"FeatureCollection","features":[{}]}}"#, features.join(","))
 }
 
-fn get_regions(grid: &Vec<Vec<char>>) -> Vec<Region> {
-    
-    let mut visited = HashSet::new();
-    let mut regions = Vec::new();
-    for (y, row) in grid.iter().enumerate() {
-        for (x, _plot) in row.iter().enumerate() {
-            let Some(plant_type) = get_plant_type(x, y, &grid) else {
-                continue;
-            };
-            if !visited.contains(&(x, y)) {
-                let mut next_region = Region {
-                    _plant_type: plant_type,
-                    plots: HashMap::new(),
-                    sides: 0,
-                    visited_sides: HashSet::new(),
-                };
-                walk_region(x, y, plant_type, &grid, &mut visited, &mut next_region);
-                regions.push(next_region);
+// every region as one path filled by plant type, holes cut out by the even-odd rule
+pub fn render_svg(grid: &[Vec<char>], polygons: &[Polygon]) -> String {
+    const CELL: usize = 16;
+    let height = grid.len();
+    let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width * CELL,
+        height * CELL,
+        width,
+        height
+    )
+    .unwrap();
+    for polygon in polygons {
+        let mut d = String::new();
+        for ring in polygon.rings() {
+            for (i, (x, y)) in ring.iter().enumerate() {
+                write!(d, "{}{} {} ", if i == 0 { 'M' } else { 'L' }, x, y).unwrap();
             }
+            d.push('Z');
         }
+        let hue = (polygon.plant_type as u32 * 47) % 360;
+        writeln!(
+            out,
+            r#"<path d="{}" fill="hsl({}, 60%, 75%)" fill-rule="evenodd" stroke="black" stroke-width="0.08"><title>{} #{}</title></path>"#,
+            d, hue, xml_char(polygon.plant_type), polygon.id
+        )
+        .unwrap();
     }
-    regions
+    out.push_str("</svg>\n");
+    out
 }
 
-fn get_total_fencing_price(grid: &Vec<Vec<char>>) -> usize {
-    let regions = get_regions(grid);
-    let price = regions.iter().fold(0, |acc, e| {
-        let (plots, perims) = e.plots.values().fold((0, 0), |(plots, perims), e| {
-            (plots + 1, perims + e.perimiters)
-        });
-        let price = plots * perims;
-        acc + price
-    });
-    price
+fn get_total_fencing_price(grid: &[Vec<char>]) -> usize {
+    region_stats(grid).iter().map(|r| r.area * r.perimeter).sum()
 }
 
-fn get_bulk_discounted_total_fencing_price(grid: &Vec<Vec<char>>) -> usize {
-    let regions = get_regions(grid);
-    let price = regions.iter().fold(0, |acc, e| {
-        let plots = e.plots.len();
-        let price = plots * e.sides;
-        acc + price
-    });
-    price
+fn get_bulk_discounted_total_fencing_price(grid: &[Vec<char>]) -> usize {
+    region_stats(grid).iter().map(|r| r.area * r.sides).sum()
 }
 
 #[aoc(day12, part1)]
-fn part1(inp: &Vec<Vec<char>>) -> usize {
+fn part1(inp: &[Vec<char>]) -> usize {
     get_total_fencing_price(inp)
 }
 
 #[aoc(day12, part2)]
-fn part2(inp: &Vec<Vec<char>>) -> usize {
+fn part2(inp: &[Vec<char>]) -> usize {
     get_bulk_discounted_total_fencing_price(inp)
 }
 
@@ -317,4 +350,72 @@ MMMISSJEEE";
         let inp = parse_input(TEST_INPUT);
         assert_eq!(part2(&inp), 1206);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_small_gardens() {
+        let inp = parse_input("AAAA\nBBCD\nBBCC\nEEEC");
+        assert_eq!((part1(&inp), part2(&inp)), (140, 80));
+        let inp = parse_input("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO");
+        assert_eq!((part1(&inp), part2(&inp)), (772, 436));
+        let inp = parse_input("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE");
+        assert_eq!(part2(&inp), 236);
+        let inp = parse_input("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA");
+        assert_eq!(part2(&inp), 368);
+    }
+
+    #[test]
+    fn test_region_stats() {
+        let stats = region_stats(&parse_input("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO"));
+        assert_eq!(stats.len(), 5);
+        assert_eq!(stats[0], RegionStats { id: 0, plant_type: 'O', area: 21, perimeter: 36, sides: 20, holes: 4 });
+        assert_eq!(stats[1], RegionStats { id: 1, plant_type: 'X', area: 1, perimeter: 4, sides: 4, holes: 0 });
+
+        // the B blocks touch diagonally and are two separate holes
+        let stats = region_stats(&parse_input("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA"));
+        assert_eq!((stats[0].area, stats[0].sides, stats[0].holes), (28, 12, 2));
+
+        // a pocket only touching the outside at a corner is still enclosed
+        let stats = region_stats(&parse_input("AAA\nA.A\nAA."));
+        assert_eq!((stats[0].area, stats[0].perimeter, stats[0].sides, stats[0].holes), (7, 16, 10, 1));
+        assert_eq!(label_regions(&parse_input("AAA\nA.A\nAA.")), vec![vec![0, 0, 0], vec![0, 1, 0], vec![0, 0, 2]]);
+    }
+
+    #[test]
+    fn test_polygons() {
+        let polygons = region_polygons(&parse_input("AAA\nA.A\nAA."));
+        assert_eq!(polygons[0].outer, vec![(0, 0), (3, 0), (3, 2), (2, 2), (2, 3), (0, 3)]);
+        assert_eq!(polygons[0].holes, vec![vec![(2, 1), (1, 1), (1, 2), (2, 2)]]);
+        assert_eq!(polygons[2].outer, vec![(2, 2), (3, 2), (3, 3), (2, 3)]);
+
+        for garden in [TEST_INPUT, "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA", "OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO"] {
+            let grid = parse_input(garden);
+            for (polygon, stats) in region_polygons(&grid).iter().zip(region_stats(&grid)) {
+                assert_eq!(polygon.vertex_count(), stats.sides);
+                assert_eq!(polygon.holes.len(), stats.holes);
+                assert_eq!(doubled_area(&polygon.outer) + polygon.holes.iter().map(|h| doubled_area(h)).sum::<i64>(), 2 * stats.area as i64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let grid = parse_input("AAA\nA.A\nAA.");
+        let polygons = region_polygons(&grid);
+        let geojson = to_geojson(&polygons[1..2]);
+        assert_eq!(
+            geojson,
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{"id":1,"plant":"."},"geometry":{"type":"Polygon","coordinates":[[[1,1],[2,1],[2,2],[1,2],[1,1]]]}}]}"#
+        );
+        assert_eq!(to_geojson(&polygons).matches(r#""type":"Feature""#).count(), 3);
+
+        let svg = render_svg(&grid, &polygons);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<path").count(), 3);
+        assert!(svg.contains(r#"d="M0 0 L3 0 L3 2 L2 2 L2 3 L0 3 ZM2 1 L1 1 L1 2 L2 2 Z""#));
+
+        let grid = parse_input("<&");
+        let svg = render_svg(&grid, &region_polygons(&grid));
+        assert!(svg.contains("<title>&lt; #0</title>"));
+        assert!(svg.contains("<title>&amp; #1</title>"));
+    }
+}
@@ -1,7 +1,9 @@
+use std::{fmt, fs::File, io::{self, BufWriter, Write}, path::Path};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Direction {
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -9,351 +11,442 @@ enum Direction {
 }
 
 impl Direction {
-    fn from_char(c: char) -> Direction {
+    fn from_char(c: char) -> Option<Direction> {
         match c {
-            '^' => Direction::Up,
-            'v' => Direction::Down,
-            '<' => Direction::Left,
-            '>' => Direction::Right,
-            _ => panic!("Invalid direction"),
+            '^' => Some(Direction::Up),
+            'v' => Some(Direction::Down),
+            '<' => Some(Direction::Left),
+            '>' => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
         }
-    }    
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum TileType {
+pub enum TileType {
     Wall,
     Empty,
     Robot,
     Box,
     BoxRight,
     BoxLeft,
+    BoxMiddle,
 }
 
 impl TileType {
-    fn from_char(c: char) -> TileType {
+    fn from_char(c: char) -> Option<TileType> {
         match c {
-            '#' => TileType::Wall,
-            '.' => TileType::Empty,
-            '@' => TileType::Robot,
-            'O' => TileType::Box,
-            ']' => TileType::BoxRight,
-            '[' => TileType::BoxLeft,
-            _ => panic!("Invalid tile type"),
+            '#' => Some(TileType::Wall),
+            '.' => Some(TileType::Empty),
+            '@' => Some(TileType::Robot),
+            'O' => Some(TileType::Box),
+            ']' => Some(TileType::BoxRight),
+            '[' => Some(TileType::BoxLeft),
+            '=' => Some(TileType::BoxMiddle),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            TileType::Wall => '#',
+            TileType::Empty => '.',
+            TileType::Robot => '@',
+            TileType::Box => 'O',
+            TileType::BoxRight => ']',
+            TileType::BoxLeft => '[',
+            TileType::BoxMiddle => '=',
         }
     }
 }
 
-struct Input {
-    grid: Vec<Vec<TileType>>,
-    moves: Vec<Direction>,
-    start_pos: (usize, usize),
+// a box covering `width` x `height` cells with its top left corner at (x, y)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crate {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
 }
 
-#[aoc_generator(day15, part1)]
-fn parse_inputs(inp: &str) -> Input {
-    let mut grid = Vec::new();
-    let mut moves = Vec::new();
-    let mut switch = false;
-    let mut start_pos = (0, 0);
-
-     for (y, line) in inp.lines().enumerate() {
-        let line = line.trim().to_string();
-        if line.is_empty() {
-           switch = true;
-           continue; 
-        }
-        
-        if !switch {
-            let mut row = Vec::new();
-            for (x, c) in line.chars().enumerate() {
-                let tile = TileType::from_char(c);
-                if tile == TileType::Robot {
-                    start_pos = (x, y);
-                }
-                row.push(tile);
-            }
-            grid.push(row);
-        } else {
-            for c in line.chars() {
-                moves.push(Direction::from_char(c));
-            }
-        }
+impl Crate {
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height).flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
     }
-    Input {grid, moves, start_pos}
-}
 
-fn scale_up(c: char) -> Vec<char> {
-    match c {
-        '#' => vec!['#', '#'],   // Wall doubles horizontally
-        'O' => vec!['[', ']'],   // Box becomes "[ ]"
-        '.' => vec!['.', '.'],   // Floor doubles horizontally
-        '@' => vec!['@', '.'],   // Robot becomes "@."
-        // anything else (like newlines, etc.) just return as is:
-        _ => vec![c],
+    fn gps(&self) -> usize {
+        self.x + self.y * 100
     }
 }
 
-#[aoc_generator(day15, part2)]
-fn parse_inputs_scaleup(inp: &str) -> Input{
-    let (warehouse, path) = inp.split_once("\n\n")
-        .expect("Expected empty line separating warehouse from path");
-    let mut grid = Vec::new();
-    let mut start_pos = (0, 0);
-
-    for (y, line) in warehouse.lines().enumerate() {
-        let scaled_chars: Vec<char> = line
-            .chars()
-            .flat_map(scale_up)
-            .collect();
-        let mut row = Vec::new();
-        for (x, c) in scaled_chars.iter().enumerate() {
-            let tile = TileType::from_char(*c);
-            if tile == TileType::Robot {
-                start_pos = (x, y);
-            }
-            row.push(tile);
+// how every tile of the map is blown up: the part 1 map is 1x1, the part 2 map 2x1;
+// walls and floor fill the whole block, a crate grows by the same factors
+// and the robot stays in the top left cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarehouseError {
+    MissingMoves,
+    InvalidTile { x: usize, y: usize, c: char },
+    InvalidMove(char),
+    UnclosedBox { x: usize, y: usize },
+    UnopenedBox { x: usize, y: usize },
+    NotRectangular { y: usize },
+    RobotCount(usize),
+    NotEnclosed { x: usize, y: usize },
+}
+
+impl fmt::Display for WarehouseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarehouseError::MissingMoves => write!(f, "expected an empty line separating the warehouse from the moves"),
+            WarehouseError::InvalidTile { x, y, c } => write!(f, "invalid tile {:?} at ({}, {})", c, x, y),
+            WarehouseError::InvalidMove(c) => write!(f, "invalid move {:?}", c),
+            WarehouseError::UnclosedBox { x, y } => write!(f, "box starting at ({}, {}) has no closing ']'", x, y),
+            WarehouseError::UnopenedBox { x, y } => write!(f, "box part at ({}, {}) has no opening '['", x, y),
+            WarehouseError::NotRectangular { y } => write!(f, "row {} has a different width than the first row", y),
+            WarehouseError::RobotCount(n) => write!(f, "expected exactly one robot, found {}", n),
+            WarehouseError::NotEnclosed { x, y } => write!(f, "border tile at ({}, {}) is not a wall", x, y),
         }
-        grid.push(row);
     }
-    let moves: Vec<Direction> = path
-    .lines()
-    .flat_map(|line| line.chars())  // flatten each line
-    .map(Direction::from_char)
-    .collect();
-
-    Input { grid, moves, start_pos }
 }
 
-fn move_robot(area_map: &mut Vec<Vec<TileType>>, start_point: (usize, usize), dir: Direction) -> (usize, usize) {
-    let (next_x, next_y) = get_next_position(start_point, &dir);
+impl std::error::Error for WarehouseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warehouse {
+    walls: Vec<Vec<bool>>,
+    crates: Vec<Crate>,
+    // index into `crates` of the crate covering each cell
+    occupied: Vec<Vec<Option<usize>>>,
+    robot: (usize, usize),
+}
 
-    // Boundary check
-    if !is_within_bounds(area_map, next_x, next_y) {
-        panic!("Out of bounds");
+impl Warehouse {
+    fn new(walls: Vec<Vec<bool>>, crates: Vec<Crate>, robot: (usize, usize)) -> Warehouse {
+        let mut occupied = vec![vec![None; walls.first().map_or(0, |row| row.len())]; walls.len()];
+        for (i, c) in crates.iter().enumerate() {
+            for (x, y) in c.cells() {
+                occupied[y][x] = Some(i);
+            }
+        }
+        Warehouse { walls, crates, occupied, robot }
     }
 
-    match area_map[next_y][next_x] {
-        TileType::Empty => {
-            area_map[next_y][next_x] = TileType::Robot;
-            area_map[start_point.1][start_point.0] = TileType::Empty;
-            return (next_x, next_y);
+    // checks that the map is a rectangle enclosed by walls holding exactly one robot;
+    // `[`, `=` and `]` runs in a row are read as one crate, so already widened maps can be loaded too
+    pub fn parse(map: &str) -> Result<Warehouse, WarehouseError> {
+        let mut tiles = Vec::new();
+        for (y, line) in map.lines().enumerate() {
+            let row = line
+                .chars()
+                .enumerate()
+                .map(|(x, c)| TileType::from_char(c).ok_or(WarehouseError::InvalidTile { x, y, c }))
+                .collect::<Result<Vec<_>, _>>()?;
+            tiles.push(row);
         }
-        TileType::Box => {
-            if push_box(area_map, (next_x, next_y), dir) {
-                area_map[next_y][next_x] = TileType::Robot;
-                area_map[start_point.1][start_point.0] = TileType::Empty;
-                return (next_x, next_y);
+        let width = tiles.first().map_or(0, |row| row.len());
+        if let Some(y) = tiles.iter().position(|row| row.len() != width) {
+            return Err(WarehouseError::NotRectangular { y });
+        }
+        let height = tiles.len();
+        for (y, row) in tiles.iter().enumerate() {
+            for (x, &tile) in row.iter().enumerate() {
+                let on_border = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+                if on_border && tile != TileType::Wall {
+                    return Err(WarehouseError::NotEnclosed { x, y });
+                }
             }
-            return start_point;
         }
-        TileType::BoxLeft | TileType::BoxRight => {
-
-            match dir {
-                Direction::Up | Direction::Down => {
-                    if v_push_box(area_map, (next_x, next_y), &dir) {
-                        area_map[next_y][next_x] = TileType::Robot;
-                        area_map[start_point.1][start_point.0] = TileType::Empty;
-                        return (next_x, next_y);
-                    } else {
-                        return start_point;
+
+        let mut walls = vec![vec![false; width]; height];
+        let mut crates = Vec::new();
+        let mut robots = Vec::new();
+        for (y, row) in tiles.iter().enumerate() {
+            // the first column after the last crate opened by a `[` in this row
+            let mut crate_end = 0;
+            for (x, &tile) in row.iter().enumerate() {
+                match tile {
+                    TileType::Wall => walls[y][x] = true,
+                    TileType::Robot => robots.push((x, y)),
+                    TileType::Box => crates.push(Crate { x, y, width: 1, height: 1 }),
+                    TileType::BoxLeft => {
+                        let len = row[x + 1..]
+                            .iter()
+                            .position(|&t| t != TileType::BoxMiddle)
+                            .filter(|&i| row[x + 1 + i] == TileType::BoxRight)
+                            .ok_or(WarehouseError::UnclosedBox { x, y })?;
+                        crates.push(Crate { x, y, width: len + 2, height: 1 });
+                        crate_end = x + len + 2;
                     }
-                }
-                Direction::Left | Direction::Right => {
-                    if push_box(area_map, (next_x, next_y), dir) {
-                        area_map[next_y][next_x] = TileType::Robot;
-                        area_map[start_point.1][start_point.0] = TileType::Empty;
-                        return (next_x, next_y);
-                    } else {
-                        return start_point;
+                    TileType::BoxMiddle | TileType::BoxRight if x >= crate_end => {
+                        return Err(WarehouseError::UnopenedBox { x, y });
                     }
-
+                    TileType::Empty | TileType::BoxMiddle | TileType::BoxRight => {}
                 }
             }
         }
-        _ => {
-            return start_point;
-        } 
+        match robots[..] {
+            [robot] => Ok(Warehouse::new(walls, crates, robot)),
+            _ => Err(WarehouseError::RobotCount(robots.len())),
+        }
+    }
+
+    // panics on a zero scale, which would leave no cells at all
+    pub fn scaled(&self, scale: Scale) -> Warehouse {
+        assert!(scale.x >= 1 && scale.y >= 1, "scale has to be at least 1x1, got {}x{}", scale.x, scale.y);
+        let walls = self
+            .walls
+            .iter()
+            .flat_map(|row| {
+                let wide_row = row.iter().flat_map(|&w| std::iter::repeat_n(w, scale.x)).collect::<Vec<_>>();
+                std::iter::repeat_n(wide_row, scale.y)
+            })
+            .collect();
+        let crates = self
+            .crates
+            .iter()
+            .map(|c| Crate { x: c.x * scale.x, y: c.y * scale.y, width: c.width * scale.x, height: c.height * scale.y })
+            .collect();
+        Warehouse::new(walls, crates, (self.robot.0 * scale.x, self.robot.1 * scale.y))
+    }
+
+    // the part 2 warehouse: everything twice as wide
+    pub fn widen(&self) -> Warehouse {
+        self.scaled(Scale { x: 2, y: 1 })
+    }
+
+    pub fn robot(&self) -> (usize, usize) {
+        self.robot
     }
-}
 
-fn push_box(area_map: &mut Vec<Vec<TileType>>, start_point: (usize, usize), dir: Direction) -> bool {
-    let (next_x, next_y) = get_next_position(start_point, &dir);
+    pub fn crates(&self) -> &[Crate] {
+        &self.crates
+    }
 
-    // Boundary check
-    if !is_within_bounds(area_map, next_x, next_y) {
-        return false;
+    // every crate the robot would shove when stepping in `dir`, or `None` if a wall blocks the push;
+    // crates push whatever they overlap after the step, so the search follows all of their cells
+    fn crates_to_push(&self, dir: Direction) -> Option<Vec<usize>> {
+        let mut pushed = Vec::new();
+        let mut frontier = vec![get_next_position(self.robot, dir)];
+        while let Some((x, y)) = frontier.pop() {
+            if self.walls[y][x] {
+                return None;
+            }
+            if let Some(i) = self.occupied[y][x] {
+                if pushed.contains(&i) {
+                    continue;
+                }
+                pushed.push(i);
+                frontier.extend(self.crates[i].cells().map(|cell| get_next_position(cell, dir)));
+            }
+        }
+        Some(pushed)
     }
 
-    match area_map[next_y][next_x] {
-        TileType::Empty => {
-            area_map[next_y][next_x] =  area_map[start_point.1][start_point.0];
-            area_map[start_point.1][start_point.0] = TileType::Empty;
-            true
+    fn shift_crates(&mut self, ids: &[usize], dir: Direction) {
+        for &i in ids {
+            for (x, y) in self.crates[i].cells() {
+                self.occupied[y][x] = None;
+            }
         }
-        TileType::Box | TileType::BoxLeft | TileType::BoxRight => {
-            if push_box(area_map, (next_x, next_y), dir) {
-                area_map[next_y][next_x] = area_map[start_point.1][start_point.0];
-                area_map[start_point.1][start_point.0] = TileType::Empty;
-                true
-            } else {
-                false
+        for &i in ids {
+            let c = &mut self.crates[i];
+            (c.x, c.y) = get_next_position((c.x, c.y), dir);
+            for (x, y) in self.crates[i].cells() {
+                self.occupied[y][x] = Some(i);
             }
         }
-        _ => false,
     }
-}
 
-fn v_push_box(area_map: &mut Vec<Vec<TileType>>, start: (usize, usize), dir: &Direction) -> bool {
-    if !matches!(area_map[start.1][start.0], TileType::BoxLeft | TileType::BoxRight) {
-        return false;
+    // moves the robot one step, pushing crates along; returns the ids of the pushed crates
+    // or `None` when the robot is blocked and nothing moved
+    pub fn move_robot(&mut self, dir: Direction) -> Option<Vec<usize>> {
+        let pushed = self.crates_to_push(dir)?;
+        self.shift_crates(&pushed, dir);
+        self.robot = get_next_position(self.robot, dir);
+        Some(pushed)
     }
-    if !can_v_push_box(area_map, start, dir) {
-        return false;
+
+    // reverts a move recorded by `Replay`, the pushed crates step back along with the robot
+    fn undo_move(&mut self, record: &MoveRecord) {
+        if let Some(pushed) = &record.pushed {
+            self.shift_crates(pushed, record.dir.opposite());
+            self.robot = record.robot_from;
+        }
     }
-    do_v_push_box(area_map, start, dir);
-    true
-}
 
-fn can_v_push_box(area_map: &Vec<Vec<TileType>>, start: (usize, usize), dir: &Direction) -> bool {
-    use TileType::*;
+    pub fn gps_sum(&self) -> usize {
+        self.crates.iter().map(Crate::gps).sum()
+    }
 
-    let (bx, by) = start;
-    let is_left  = matches!(area_map[by][bx], BoxLeft);
-    let is_right = matches!(area_map[by][bx], BoxRight);
-    if !is_left && !is_right {
-        return false;
+    pub fn tiles(&self) -> Vec<Vec<TileType>> {
+        let mut tiles = self
+            .walls
+            .iter()
+            .map(|row| row.iter().map(|&w| if w { TileType::Wall } else { TileType::Empty }).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        for c in &self.crates {
+            for (x, y) in c.cells() {
+                tiles[y][x] = match (c.width, x - c.x) {
+                    (1, _) => TileType::Box,
+                    (_, 0) => TileType::BoxLeft,
+                    (w, dx) if dx == w - 1 => TileType::BoxRight,
+                    _ => TileType::BoxMiddle,
+                };
+            }
+        }
+        tiles[self.robot.1][self.robot.0] = TileType::Robot;
+        tiles
     }
+}
 
-    let partner_x = if is_left { bx + 1 } else { bx - 1 };
-    let partner_y = by;
+impl fmt::Display for Warehouse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.tiles() {
+            writeln!(f, "{}", row.iter().map(|t| t.to_char()).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
 
-    if is_left && area_map[partner_y][partner_x] != BoxRight { return false; }
-    if is_right && area_map[partner_y][partner_x] != BoxLeft { return false; }
+// one robot move; `pushed` is `None` when the robot was blocked and nothing changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub dir: Direction,
+    pub robot_from: (usize, usize),
+    pub pushed: Option<Vec<usize>>,
+}
 
-    let (nx1, ny1) = get_next_position((bx, by), dir);
-    let (nx2, ny2) = get_next_position((partner_x, partner_y), dir);
+// a warehouse together with the moves applied to it, which can be stepped back and forth
+#[derive(Debug, Clone)]
+pub struct Replay {
+    warehouse: Warehouse,
+    history: Vec<MoveRecord>,
+    undone: Vec<MoveRecord>,
+}
 
-    if !is_within_bounds(area_map, nx1, ny1) || !is_within_bounds(area_map, nx2, ny2) {
-        return false;
+impl Replay {
+    pub fn new(warehouse: Warehouse) -> Replay {
+        Replay { warehouse, history: Vec::new(), undone: Vec::new() }
     }
 
-    match area_map[ny1][nx1] {
-        Empty => {}
-        BoxLeft | BoxRight => {
-            if !can_v_push_box(area_map, (nx1, ny1), dir) {
-                return false;
-            }
-        }
-        _ => return false,
+    pub fn warehouse(&self) -> &Warehouse {
+        &self.warehouse
     }
 
-    match area_map[ny2][nx2] {
-        Empty => {}
-        BoxLeft | BoxRight => {
-            if !can_v_push_box(area_map, (nx2, ny2), dir) {
-                return false;
-            }
-        }
-        _ => return false,
+    pub fn history(&self) -> &[MoveRecord] {
+        &self.history
     }
 
-    true
-}
+    // applies a new move, which drops anything that could still be redone
+    pub fn step(&mut self, dir: Direction) -> &MoveRecord {
+        self.undone.clear();
+        self.apply(dir)
+    }
 
-fn do_v_push_box(area_map: &mut Vec<Vec<TileType>>, start: (usize, usize), dir: &Direction) {
-    use TileType::*;
+    fn apply(&mut self, dir: Direction) -> &MoveRecord {
+        let robot_from = self.warehouse.robot;
+        let pushed = self.warehouse.move_robot(dir);
+        self.history.push(MoveRecord { dir, robot_from, pushed });
+        self.history.last().unwrap()
+    }
 
-    let (bx, by) = start;
-    let is_left  = matches!(area_map[by][bx], BoxLeft);
-    let partner_x = if is_left { bx + 1 } else { bx - 1 };
-    let partner_y = by;
-    let (nx1, ny1) = get_next_position((bx, by), dir);
-    let (nx2, ny2) = get_next_position((partner_x, partner_y), dir);
-    if matches!(area_map[ny1][nx1], BoxLeft | BoxRight) {
-        do_v_push_box(area_map, (nx1, ny1), dir);
+    pub fn undo(&mut self) -> Option<&MoveRecord> {
+        let record = self.history.pop()?;
+        self.warehouse.undo_move(&record);
+        self.undone.push(record);
+        self.undone.last()
     }
-    if matches!(area_map[ny2][nx2], BoxLeft | BoxRight) {
-        do_v_push_box(area_map, (nx2, ny2), dir);
+
+    pub fn redo(&mut self) -> Option<&MoveRecord> {
+        let record = self.undone.pop()?;
+        Some(self.apply(record.dir))
     }
 
-    let (lx, ly)    = if is_left { (bx, by) } else { (partner_x, partner_y) };
-    let (rx, ry)    = if is_left { (partner_x, partner_y) } else { (bx, by) };
-    let (lnx, lny)  = get_next_position((lx, ly), dir);
-    let (rnx, rny)  = get_next_position((rx, ry), dir);
+    // plays all moves, writing the map after every one of them
+    pub fn write_frames(&mut self, moves: &[Direction], out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Initial state:\n{}", self.warehouse)?;
+        for &dir in moves {
+            let record = self.step(dir);
+            let status = if record.pushed.is_some() { "" } else { " (blocked)" };
+            writeln!(out, "Move {} {}{}:", self.history.len(), dir.to_char(), status)?;
+            writeln!(out, "{}", self.warehouse)?;
+        }
+        Ok(())
+    }
 
-    area_map[ly][lx]   = Empty;
-    area_map[ry][rx]   = Empty;
-    area_map[lny][lnx] = BoxLeft;
-    area_map[rny][rnx] = BoxRight;
+    pub fn save_frames(&mut self, moves: &[Direction], path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_frames(moves, &mut out)?;
+        out.flush()
+    }
 }
 
-fn get_next_position(start_point: (usize, usize), dir: &Direction) -> (usize, usize) {
-    match dir {
-        &Direction::Up => (start_point.0, start_point.1.saturating_sub(1)),
-        &Direction::Down => (start_point.0, start_point.1 + 1),
-        &Direction::Left => (start_point.0.saturating_sub(1), start_point.1),
-        &Direction::Right => (start_point.0 + 1, start_point.1),
-    }
+struct Input {
+    warehouse: Warehouse,
+    moves: Vec<Direction>,
 }
 
-fn is_within_bounds(area_map: &Vec<Vec<TileType>>, x: usize, y: usize) -> bool {
-    y < area_map.len() && x < area_map[0].len()
+#[aoc_generator(day15)]
+fn parse_inputs(inp: &str) -> Result<Input, WarehouseError> {
+    let inp = inp.replace("\r\n", "\n");
+    let (map, path) = inp.split_once("\n\n").ok_or(WarehouseError::MissingMoves)?;
+    let moves = path
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| Direction::from_char(c).ok_or(WarehouseError::InvalidMove(c)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Input { warehouse: Warehouse::parse(map)?, moves })
 }
 
-fn calculate_box_coords_sum(area_map: &Vec<Vec<TileType>>) -> i32{
-    let mut box_sum = 0;
-    
-    for (y, row) in area_map.iter().enumerate() {
-        for (x, tile) in row.iter().enumerate() {
-            if *tile == TileType::Box || *tile == TileType::BoxLeft {
-                box_sum += x as i32 + y as i32 * 100;
-            }
-        }
+fn get_next_position(start_point: (usize, usize), dir: Direction) -> (usize, usize) {
+    match dir {
+        Direction::Up => (start_point.0, start_point.1.saturating_sub(1)),
+        Direction::Down => (start_point.0, start_point.1 + 1),
+        Direction::Left => (start_point.0.saturating_sub(1), start_point.1),
+        Direction::Right => (start_point.0 + 1, start_point.1),
     }
-    box_sum
 }
 
-fn visualize_map(area_map: &Vec<Vec<TileType>>) {
-    for row in area_map {
-        for tile in row {
-            match tile {
-                TileType::Wall => print!("#"),
-                TileType::Empty => print!("."),
-                TileType::Robot => print!("@"),
-                TileType::Box => print!("O"),
-                TileType::BoxRight => print!("]"),
-                TileType::BoxLeft => print!("["),
-            }
-        }
-        println!();
+fn run_moves(mut warehouse: Warehouse, moves: &[Direction]) -> usize {
+    for &dir in moves {
+        warehouse.move_robot(dir);
     }
+    warehouse.gps_sum()
 }
 
 #[aoc(day15, part1)]
-fn part1(input: &Input) -> i32 {
-    let mut area_map = input.grid.clone();
-    let mut robot_pos = input.start_pos;
-
-    for dir in &input.moves {
-        robot_pos = move_robot(&mut area_map, robot_pos, dir.clone());
-    }
-
-    visualize_map(&area_map);
-    calculate_box_coords_sum(&area_map)
+fn part1(input: &Input) -> usize {
+    run_moves(input.warehouse.clone(), &input.moves)
 }
 
 #[aoc(day15, part2)]
-fn part2(input: &Input) -> i32 {
-    let mut area_map = input.grid.clone();
-    let mut robot_pos = input.start_pos;
-    
-    for dir in &input.moves {
-        robot_pos = move_robot(&mut area_map, robot_pos, dir.clone());
-        
-    }
-    visualize_map(&area_map);
-    calculate_box_coords_sum(&area_map)
+fn part2(input: &Input) -> usize {
+    run_moves(input.warehouse.widen(), &input.moves)
 }
 
 
@@ -395,20 +488,133 @@ const TEST_INPUT2: &str = "#######
 
     #[test]
     fn test_part1() {
-        let input = parse_inputs(TEST_INPUT);
+        let input = parse_inputs(TEST_INPUT).unwrap();
         assert_eq!(part1(&input), 10092);
     }
 
     #[test]
     fn test_part2() {
-        let input = parse_inputs_scaleup(TEST_INPUT);
+        let input = parse_inputs(TEST_INPUT).unwrap();
         assert_eq!(part2(&input), 9021);
     }
 
 
     #[test]
     fn test_scale(){
-        let inp = parse_inputs_scaleup(TEST_INPUT2);
+        let inp = parse_inputs(TEST_INPUT2).unwrap();
         part2(&inp);
     }
+
+    #[test]
+    fn test_scale_display() {
+        let inp = parse_inputs(TEST_INPUT2).unwrap();
+        let mut warehouse = inp.warehouse.widen();
+        for &dir in &inp.moves {
+            warehouse.move_robot(dir);
+        }
+        assert_eq!(
+            warehouse.to_string(),
+            "##############
+##......##..##
+##...[][]...##
+##....[]....##
+##....@.....##
+##..........##
+##############
+"
+        );
+    }
+
+    #[test]
+    fn test_replay_undo_redo() {
+        let inp = parse_inputs(TEST_INPUT).unwrap();
+        let mut replay = Replay::new(inp.warehouse.widen());
+        let mut states = vec![replay.warehouse().clone()];
+        for &dir in &inp.moves {
+            replay.step(dir);
+            states.push(replay.warehouse().clone());
+        }
+        assert_eq!(replay.warehouse().gps_sum(), 9021);
+        for expected in states.iter().rev().skip(1) {
+            replay.undo();
+            assert_eq!(replay.warehouse(), expected);
+        }
+        assert!(replay.undo().is_none());
+        replay.redo();
+        replay.redo();
+        assert_eq!(replay.warehouse(), &states[2]);
+        assert_eq!(replay.history().len(), 2);
+        replay.step(Direction::Up);
+        assert!(replay.redo().is_none());
+    }
+
+    #[test]
+    fn test_write_frames() {
+        let inp = parse_inputs(TEST_INPUT2).unwrap();
+        let mut replay = Replay::new(inp.warehouse.clone());
+        let mut out = Vec::new();
+        replay.write_frames(&[Direction::Left, Direction::Right, Direction::Right], &mut out).unwrap();
+        let frames = String::from_utf8(out).unwrap();
+        assert!(frames.starts_with("Initial state:\n#######\n"));
+        assert!(frames.contains("Move 1 <:\n#######\n#...#.#\n#.....#\n#.OO@.#\n"));
+        assert!(frames.contains("Move 3 > (blocked):\n"));
+    }
+
+    #[test]
+    fn test_wide_and_tall_crates() {
+        let inp = parse_inputs(TEST_INPUT2).unwrap();
+        let mut warehouse = inp.warehouse.scaled(Scale { x: 3, y: 1 });
+        assert_eq!(warehouse.to_string().lines().nth(3), Some("###......[=][=]@..###"));
+        assert_eq!(warehouse.move_robot(Direction::Left), Some(vec![1, 0]));
+        assert_eq!(warehouse.to_string().lines().nth(3), Some("###.....[=][=]@...###"));
+        // pushing the lower crate up shoves both crates it overlaps above it
+        for dir in [Direction::Down, Direction::Down, Direction::Left, Direction::Left, Direction::Left] {
+            warehouse.move_robot(dir);
+        }
+        assert_eq!(warehouse.move_robot(Direction::Up), Some(vec![2, 1, 0]));
+        let map = warehouse.to_string();
+        assert_eq!(map.lines().skip(2).take(3).collect::<Vec<_>>(), vec![
+            "###.....[=][=]....###",
+            "###......[=]......###",
+            "###........@......###",
+        ]);
+        assert_eq!(warehouse.move_robot(Direction::Up), None);
+
+        let mut warehouse = inp.warehouse.scaled(Scale { x: 1, y: 2 });
+        assert_eq!(warehouse.crates()[0], Crate { x: 3, y: 6, width: 1, height: 2 });
+        warehouse.move_robot(Direction::Left);
+        assert_eq!(warehouse.crates()[0], Crate { x: 2, y: 6, width: 1, height: 2 });
+        assert_eq!(warehouse.robot(), (4, 6));
+    }
+
+    #[test]
+    fn test_widen() {
+        let narrow = Warehouse::parse("#####\n#@O.#\n#####").unwrap();
+        let wide = Warehouse::parse("##########\n##@.[]..##\n##########").unwrap();
+        assert_eq!(narrow.widen(), wide);
+        assert_eq!(wide.robot(), (2, 1));
+        let ragged = Warehouse::parse("###\n#[=]\n###");
+        assert_eq!(ragged, Err(WarehouseError::NotRectangular { y: 1 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "scale has to be at least 1x1")]
+    fn test_zero_scale() {
+        Warehouse::parse("#####\n#@O.#\n#####").unwrap().scaled(Scale { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(Warehouse::parse("####\n#..#\n####"), Err(WarehouseError::RobotCount(0)));
+        assert_eq!(Warehouse::parse("####\n#@@#\n####"), Err(WarehouseError::RobotCount(2)));
+        assert_eq!(Warehouse::parse("####\n#@..\n####"), Err(WarehouseError::NotEnclosed { x: 3, y: 1 }));
+        assert_eq!(Warehouse::parse("####\n#@x#\n####"), Err(WarehouseError::InvalidTile { x: 2, y: 1, c: 'x' }));
+        assert_eq!(Warehouse::parse("#####\n#@[.#\n#####"), Err(WarehouseError::UnclosedBox { x: 2, y: 1 }));
+        assert_eq!(Warehouse::parse("#####\n#@].#\n#####"), Err(WarehouseError::UnopenedBox { x: 2, y: 1 }));
+        assert_eq!(Warehouse::parse("######\n#@[]=#\n######"), Err(WarehouseError::UnopenedBox { x: 4, y: 1 }));
+        assert!(Warehouse::parse("######\n#@[=]#\n######").is_ok());
+        assert!(matches!(parse_inputs("####\n#@.#\n####\n<>"), Err(WarehouseError::MissingMoves)));
+        assert!(matches!(parse_inputs("####\n#@.#\n####\n\n<x"), Err(WarehouseError::InvalidMove('x'))));
+        assert_eq!(parse_inputs("####\r\n#@.#\r\n####\r\n\r\n<>\r\n>").unwrap().moves.len(), 3);
+    }
 }
@@ -1,11 +1,12 @@
-use std::{cmp::Ordering, collections::{BinaryHeap, HashSet}};
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap, HashSet}, fmt::Write};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use num::{BigUint, One, Zero};
 use pathfinding::prelude::Matrix;
 
 #[derive(Debug)]
-struct Maze {
+pub struct Maze {
     grid: Matrix<char>,
     start_pos: (usize, usize),
     stop_pos: (usize, usize),
@@ -17,27 +18,33 @@ impl Maze {
     fn is_open(&self, x: usize, y: usize) -> bool {
         self.grid[(y, x)] != '#'
     }
+}
 
-    fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize, Direction)> {
-        let dirs = [(0,-1),(1,0),(0,1),(-1,0)];
-        let mut neigh = Vec::new();
-        for &(dx,dy) in &dirs {
-            let nx = x as isize + dx;
-            let ny = y as isize + dy;
-            if nx >= 0 && nx < self.width as isize && ny >= 0 && ny < self.height as isize {
-                let nxu = nx as usize;
-                let nyu = ny as usize;
-                if self.is_open(nxu, nyu) {
-                    neigh.push((nxu, nyu, Direction::from_dxy(dx,dy)));
-                }
+fn grid_neighbors(
+    width: usize,
+    height: usize,
+    is_open: impl Fn(usize, usize) -> bool,
+    x: usize,
+    y: usize,
+) -> Vec<(usize, usize, Direction)> {
+    let dirs = [(0,-1),(1,0),(0,1),(-1,0)];
+    let mut neigh = Vec::new();
+    for &(dx,dy) in &dirs {
+        let nx = x as isize + dx;
+        let ny = y as isize + dy;
+        if nx >= 0 && nx < width as isize && ny >= 0 && ny < height as isize {
+            let nxu = nx as usize;
+            let nyu = ny as usize;
+            if is_open(nxu, nyu) {
+                neigh.push((nxu, nyu, Direction::from_dxy(dx,dy)));
             }
         }
-        neigh
     }
+    neigh
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-enum Direction {
+pub enum Direction {
     North,
     East,
     South,
@@ -59,11 +66,36 @@ impl Direction {
         }
     }
 
-    fn turn_cost(self, target: Direction) -> u32 {
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+
+    // turning on the spot to face `target`
+    fn rotation_cost(self, target: Direction, costs: &MoveCosts) -> u32 {
         if self == target {
-            1
+            0
+        } else if self == target.opposite() {
+            costs.reverse
         } else {
-            1001
+            costs.turn
+        }
+    }
+
+    fn turn_cost(self, target: Direction, costs: &MoveCosts) -> u32 {
+        costs.step + self.rotation_cost(target, costs)
+    }
+
+    fn arrow(self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
         }
     }
 
@@ -77,6 +109,37 @@ impl Direction {
     }
 }
 
+// `reverse` is what turning around on the spot costs, two 90 degree turns in the puzzle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveCosts {
+    pub step: u32,
+    pub turn: u32,
+    pub reverse: u32,
+}
+
+impl Default for MoveCosts {
+    fn default() -> Self {
+        MoveCosts { step: 1, turn: 1000, reverse: 2000 }
+    }
+}
+
+// `end_dir: None` accepts reaching the stop tile facing any direction, otherwise the reindeer
+// turns on the stop tile to face `end_dir` and pays for that turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteOptions {
+    pub costs: MoveCosts,
+    pub start_dir: Direction,
+    pub end_dir: Option<Direction>,
+}
+
+impl Default for RouteOptions {
+    fn default() -> Self {
+        RouteOptions { costs: MoveCosts::default(), start_dir: Direction::East, end_dir: None }
+    }
+}
+
+pub type MazeState = (usize, usize, Direction);
+
 #[derive(Eq, PartialEq)]
 struct State {
     cost: u32,
@@ -85,20 +148,21 @@ struct State {
     dir: Direction,
 }
 
+// reversed so the BinaryHeap pops the cheapest state first
 impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost).reverse()
+        other.cost.cmp(&self.cost)
     }
 }
 
 impl PartialOrd for State {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cost.cmp(&other.cost).reverse())
+        Some(self.cmp(other))
     }
 }
 
 #[aoc_generator(day16)]
-fn read_inputs(input: &str) -> Maze {
+pub fn read_inputs(input: &str) -> Maze {
     let mut rows = Vec::new();
     let mut start_pos = (0, 0);
     let mut stop_pos = (0, 0);
@@ -126,52 +190,114 @@ fn index_of(x: usize, y: usize, dir: Direction, width: usize) -> usize {
     (y * width + x) * 4 + dir_i
 }
 
-fn reconstruct_all_paths(
-    end_states: &[(usize, usize, Direction)],
-    start_state: (usize, usize, Direction),
-    parents: &Vec<Vec<(usize, usize, Direction)>>,
-    width: usize
-) -> Vec<Vec<(usize,usize,Direction)>> {
-    let mut results = Vec::new();
-    for &end_st in end_states {
-        dfs_all_paths(end_st, start_state, parents, &mut Vec::new(), &mut results, width);
-    }
-    results
+// dijkstra over (x, y, facing) states of any grid, `parents` keeps every equally cheap predecessor
+pub struct StateSearch {
+    width: usize,
+    start: MazeState,
+    dist: Vec<u32>,
+    parents: Vec<Vec<(usize, usize, Direction)>>,
 }
 
-fn dfs_all_paths(
-    current: (usize,usize,Direction),
-    start: (usize,usize,Direction),
-    parents: &Vec<Vec<(usize,usize,Direction)>>,
-    path: &mut Vec<(usize,usize,Direction)>,
-    results: &mut Vec<Vec<(usize,usize,Direction)>>,
-    width: usize
-) {
-    let idx = index_of(current.0, current.1, current.2, width);
-    path.push(current);
-    if current == start {
-        path.reverse();
-        results.push(path.clone());
-        path.reverse();
-    } else {
-        for &p in &parents[idx] {
-            dfs_all_paths(p, start, parents, path, results, width);
+impl StateSearch {
+    pub fn cost(&self, x: usize, y: usize, dir: Direction) -> Option<u32> {
+        let d = self.dist[index_of(x, y, dir, self.width)];
+        (d != u32::MAX).then_some(d)
+    }
+
+    // the cheapest states arriving on the stop tile, with the cost of then turning to `end_dir`
+    // included; the states keep the direction they arrived in
+    pub fn best_end_states(
+        &self,
+        (ex, ey): (usize, usize),
+        end_dir: Option<Direction>,
+        costs: &MoveCosts,
+    ) -> Option<(u32, Vec<MazeState>)> {
+        let mut best_cost = u32::MAX;
+        let mut end_states = Vec::new();
+        for d in Direction::list_all() {
+            let Some(arrival) = self.cost(ex, ey, d) else {
+                continue;
+            };
+            let cost = arrival + end_dir.map_or(0, |e| d.rotation_cost(e, costs));
+            if cost < best_cost {
+                best_cost = cost;
+                end_states.clear();
+                end_states.push((ex, ey, d));
+            } else if cost == best_cost {
+                end_states.push((ex, ey, d));
+            }
         }
+        (best_cost != u32::MAX).then_some((best_cost, end_states))
     }
-    path.pop();
-}
 
-fn solve(maze: &Maze) -> (u32, usize){
-    let (sx, sy) = maze.start_pos;
-    let (ex, ey) = maze.stop_pos;
+    // walks the parents DAG backwards from the end states, marking every state on some optimal path
+    pub fn optimal_states(&self, end_states: &[MazeState]) -> Vec<bool> {
+        let mut on_path = vec![false; self.dist.len()];
+        let mut stack = end_states.to_vec();
+        while let Some((x, y, dir)) = stack.pop() {
+            let idx = index_of(x, y, dir, self.width);
+            if on_path[idx] {
+                continue;
+            }
+            on_path[idx] = true;
+            stack.extend(self.parents[idx].iter().copied());
+        }
+        on_path
+    }
+
+    pub fn optimal_tiles(&self, end_states: &[MazeState]) -> HashSet<(usize, usize)> {
+        self.optimal_states(end_states)
+            .iter()
+            .enumerate()
+            .filter(|(_, &on_path)| on_path)
+            .map(|(idx, _)| ((idx / 4) % self.width, (idx / 4) / self.width))
+            .collect()
+    }
 
-    let width = maze.width;
-    let height = maze.height;
+    // number of distinct optimal paths, counted by visiting the optimal states in order of cost.
+    // That order only works when parents are strictly cheaper than their children; with a zero
+    // step cost optimal moves can go round in circles and there is `None` to report
+    pub fn count_optimal_paths(&self, end_states: &[MazeState]) -> Option<BigUint> {
+        let on_path = self.optimal_states(end_states);
+        let mut order = (0..self.dist.len()).filter(|&idx| on_path[idx]).collect::<Vec<_>>();
+        let free_move = order.iter().any(|&idx| {
+            self.parents[idx].iter().any(|&(px, py, pdir)| self.dist[index_of(px, py, pdir, self.width)] == self.dist[idx])
+        });
+        if free_move {
+            return None;
+        }
+        order.sort_by_key(|&idx| self.dist[idx]);
+
+        let mut num_paths = vec![BigUint::zero(); self.dist.len()];
+        let (sx, sy, sdir) = self.start;
+        num_paths[index_of(sx, sy, sdir, self.width)] = BigUint::one();
+        for idx in order {
+            for &(px, py, pdir) in &self.parents[idx] {
+                let count = num_paths[index_of(px, py, pdir, self.width)].clone();
+                num_paths[idx] += count;
+            }
+        }
+        Some(
+            end_states
+                .iter()
+                .map(|&(x, y, dir)| &num_paths[index_of(x, y, dir, self.width)])
+                .sum(),
+        )
+    }
+}
+
+pub fn turn_penalised_search(
+    width: usize,
+    height: usize,
+    is_open: impl Fn(usize, usize) -> bool,
+    (sx, sy): (usize, usize),
+    start_dir: Direction,
+    costs: &MoveCosts,
+) -> StateSearch {
     // dist array: width * height * 4 directions
     let mut dist = vec![u32::MAX; width * height * 4];
     let mut parents: Vec<Vec<(usize,usize,Direction)>> = vec![Vec::new(); width*height*4];
 
-    let start_dir = Direction::East; // start facing East
     let start_idx = index_of(sx, sy, start_dir, width);
     dist[start_idx] = 0;
 
@@ -183,8 +309,8 @@ fn solve(maze: &Maze) -> (u32, usize){
         if cost > dist[idx] {
             continue;
         }
-        for (nx, ny, ndir) in maze.neighbors(x,y) {
-            let step_cost =  ndir.turn_cost(dir);
+        for (nx, ny, ndir) in grid_neighbors(width, height, &is_open, x, y) {
+            let step_cost = dir.turn_cost(ndir, costs);
             let new_cost = cost + step_cost;
             let nidx = index_of(nx, ny, ndir, width);
             if new_cost < dist[nidx] {
@@ -199,30 +325,154 @@ fn solve(maze: &Maze) -> (u32, usize){
         }
     }
 
-    let mut best_cost = u32::MAX;
-    let mut end_states = Vec::new();
-    for d in Direction::list_all() {
-        let eidx = index_of(ex, ey,d, width);
-        if dist[eidx] < best_cost {
-            best_cost = dist[eidx];
-            end_states.clear();
-            end_states.push((ex,ey,d));
-        } else if dist[eidx] == best_cost {
-            end_states.push((ex,ey,d));
+    StateSearch { width, start: (sx, sy, start_dir), dist, parents }
+}
+
+fn solve(maze: &Maze) -> (u32, usize) {
+    solve_with(maze, &RouteOptions::default()).expect("No path through the maze")
+}
+
+fn search_maze(maze: &Maze, options: &RouteOptions) -> StateSearch {
+    turn_penalised_search(
+        maze.width,
+        maze.height,
+        |x, y| maze.is_open(x, y),
+        maze.start_pos,
+        options.start_dir,
+        &options.costs,
+    )
+}
+
+pub fn solve_with(maze: &Maze, options: &RouteOptions) -> Option<(u32, usize)> {
+    let search = search_maze(maze, options);
+    let (best_cost, end_states) = search.best_end_states(maze.stop_pos, options.end_dir, &options.costs)?;
+
+    Some((best_cost, search.optimal_tiles(&end_states).len()))
+}
+
+// `None` if there is no route, or if steps are free and the routes can not be counted
+pub fn count_best_paths(maze: &Maze, options: &RouteOptions) -> Option<BigUint> {
+    let search = search_maze(maze, options);
+    let (_, end_states) = search.best_end_states(maze.stop_pos, options.end_dir, &options.costs)?;
+    search.count_optimal_paths(&end_states)
+}
+
+// the tiles used by the optimal routes, with the directions they are entered in
+// and the tiles where some optimal route turns
+#[derive(Debug, Default)]
+pub struct RouteOverlay {
+    pub tiles: HashMap<(usize, usize), Vec<Direction>>,
+    pub turns: HashSet<(usize, usize)>,
+}
+
+pub fn route_overlay(maze: &Maze, options: &RouteOptions) -> Option<RouteOverlay> {
+    let search = search_maze(maze, options);
+    let (_, end_states) = search.best_end_states(maze.stop_pos, options.end_dir, &options.costs)?;
+    let on_path = search.optimal_states(&end_states);
+
+    let mut overlay = RouteOverlay::default();
+    for (idx, _) in on_path.iter().enumerate().filter(|(_, &on_path)| on_path) {
+        let (x, y) = ((idx / 4) % maze.width, (idx / 4) / maze.width);
+        let dir = Direction::list_all()[idx % 4];
+        let dirs = overlay.tiles.entry((x, y)).or_default();
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
         }
+        for &(px, py, pdir) in &search.parents[idx] {
+            if pdir != dir {
+                overlay.turns.insert((px, py));
+            }
+        }
+    }
+    // routes arriving facing another way still turn to `end_dir` on the stop tile
+    if end_states.iter().any(|&(_, _, d)| options.end_dir.is_some_and(|e| e != d)) {
+        overlay.turns.insert(maze.stop_pos);
     }
-    let start_state = (sx, sy, start_dir);
-    let all_paths = reconstruct_all_paths(&end_states, start_state, &parents, width);
+    Some(overlay)
+}
 
-    let mut unique_tiles = HashSet::new();
-    for path in &all_paths {
-        for &(x,y,_) in path {
-            unique_tiles.insert((x,y));
-        }
+// the character drawn for a tile, tiles entered from several directions on different routes show `+`
+fn overlay_char(maze: &Maze, overlay: &RouteOverlay, x: usize, y: usize) -> char {
+    let c = maze.grid[(y, x)];
+    match overlay.tiles.get(&(x, y)) {
+        _ if c == 'S' || c == 'E' => c,
+        Some(dirs) if dirs.len() == 1 => dirs[0].arrow(),
+        Some(_) => '+',
+        None => c,
     }
+}
 
-    (best_cost, unique_tiles.len())
+pub fn render_ansi(maze: &Maze, overlay: &RouteOverlay) -> String {
+    let mut out = String::new();
+    for y in 0..maze.height {
+        for x in 0..maze.width {
+            let c = overlay_char(maze, overlay, x, y);
+            let color = if c == 'S' || c == 'E' {
+                "\x1b[1;31m"
+            } else if overlay.turns.contains(&(x, y)) {
+                "\x1b[1;33m"
+            } else if overlay.tiles.contains_key(&(x, y)) {
+                "\x1b[32m"
+            } else if c == '#' {
+                "\x1b[90m"
+            } else {
+                ""
+            };
+            if color.is_empty() {
+                out.push(c);
+            } else {
+                write!(out, "{}{}\x1b[0m", color, c).unwrap();
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
 
+pub fn render_svg(maze: &Maze, overlay: &RouteOverlay) -> String {
+    const CELL: usize = 16;
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="12" text-anchor="middle">"#,
+        maze.width * CELL,
+        maze.height * CELL
+    )
+    .unwrap();
+    for y in 0..maze.height {
+        for x in 0..maze.width {
+            let (px, py) = (x * CELL, y * CELL);
+            let fill = if !maze.is_open(x, y) {
+                "#444"
+            } else if overlay.tiles.contains_key(&(x, y)) {
+                "#b8e6b8"
+            } else {
+                "#fff"
+            };
+            writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, px, py, CELL, CELL, fill).unwrap();
+            if overlay.turns.contains(&(x, y)) {
+                writeln!(
+                    out,
+                    r##"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="#e69500" stroke-width="2"/>"##,
+                    px + CELL / 2,
+                    py + CELL / 2,
+                    CELL / 2 - 1
+                )
+                .unwrap();
+            }
+            let c = overlay_char(maze, overlay, x, y);
+            if maze.is_open(x, y) && c != '.' {
+                let text = match c {
+                    '<' => "&lt;".to_string(),
+                    '>' => "&gt;".to_string(),
+                    _ => c.to_string(),
+                };
+                writeln!(out, r#"<text x="{}" y="{}">{}</text>"#, px + CELL / 2, py + CELL - 4, text).unwrap();
+            }
+        }
+    }
+    out.push_str("</svg>\n");
+    out
 }
 
 #[aoc(day16, part1)]
@@ -272,4 +522,112 @@ mod tests {
         let input = read_inputs(TEST_INPUT);
         assert_eq!(part2(&input), 45);
     }
+
+    #[test]
+    fn test_count_best_paths() {
+        let input = read_inputs(TEST_INPUT);
+        assert_eq!(count_best_paths(&input, &RouteOptions::default()), Some(BigUint::from(3u32)));
+        let free_steps = RouteOptions {
+            costs: MoveCosts { step: 0, ..MoveCosts::default() },
+            ..RouteOptions::default()
+        };
+        assert_eq!(count_best_paths(&input, &free_steps), None);
+    }
+
+    #[test]
+    fn test_open_maze() {
+        // every monotone staircase route is optimal when turning is free
+        let size = 40;
+        let mut rows = vec!["#".repeat(size + 2)];
+        for y in 0..size {
+            let mut row = ".".repeat(size);
+            if y == 0 {
+                row.replace_range(size - 1.., "E");
+            }
+            if y == size - 1 {
+                row.replace_range(..1, "S");
+            }
+            rows.push(format!("#{}#", row));
+        }
+        rows.push("#".repeat(size + 2));
+        let input = read_inputs(&rows.join("\n"));
+        let options = RouteOptions {
+            costs: MoveCosts { step: 1, turn: 0, reverse: 0 },
+            ..RouteOptions::default()
+        };
+        assert_eq!(solve_with(&input, &options), Some((2 * (size as u32 - 1), size * size)));
+        // binomial(78, 39)
+        let expected = (1..=39u32).fold(BigUint::one(), |acc, k| acc * (39 + k) / k);
+        assert_eq!(count_best_paths(&input, &options), Some(expected));
+    }
+
+    fn strip_ansi(text: &str) -> String {
+        let mut out = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_render() {
+        let input = read_inputs("######\n#...E#\n#S####\n######");
+        let overlay = route_overlay(&input, &RouteOptions::default()).unwrap();
+        assert_eq!(overlay.turns, HashSet::from([(1, 2), (1, 1)]));
+        assert_eq!(strip_ansi(&render_ansi(&input, &overlay)), "######\n#^>>E#\n#S####\n######\n");
+        let svg = render_svg(&input, &overlay);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert_eq!(svg.matches("&gt;").count(), 2);
+        let facing_south = RouteOptions { end_dir: Some(Direction::South), ..RouteOptions::default() };
+        let overlay = route_overlay(&input, &facing_south).unwrap();
+        assert_eq!(overlay.turns, HashSet::from([(1, 2), (1, 1), (4, 1)]));
+    }
+
+    #[test]
+    fn test_render_example() {
+        let input = read_inputs(TEST_INPUT);
+        let overlay = route_overlay(&input, &RouteOptions::default()).unwrap();
+        assert_eq!(overlay.tiles.len(), 45);
+    }
+
+    #[test]
+    fn test_route_options() {
+        let input = read_inputs(TEST_INPUT);
+        let cheap_turns = RouteOptions {
+            costs: MoveCosts { step: 1, turn: 0, reverse: 0 },
+            ..RouteOptions::default()
+        };
+        assert_eq!(solve_with(&input, &cheap_turns).map(|r| r.0), Some(28));
+        let facing_east = RouteOptions { end_dir: Some(Direction::East), ..RouteOptions::default() };
+        // the best route arrives facing north and turns once more on the stop tile
+        assert_eq!(solve_with(&input, &facing_east), Some((8036, 45)));
+        let facing_north = RouteOptions {
+            start_dir: Direction::North,
+            end_dir: Some(Direction::North),
+            ..RouteOptions::default()
+        };
+        assert_eq!(solve_with(&input, &facing_north).map(|r| r.0), Some(6036));
+    }
+
+    #[test]
+    fn test_turning_around() {
+        // the only way out is behind the reindeer, which takes two turns
+        let input = read_inputs("#####\n#E.S#\n#####");
+        assert_eq!(solve_with(&input, &RouteOptions::default()).map(|r| r.0), Some(2002));
+    }
+
+    #[test]
+    fn test_turn_on_stop_tile() {
+        let input = read_inputs("#####\n#S.E#\n#####");
+        let facing = |d| RouteOptions { end_dir: Some(d), ..RouteOptions::default() };
+        assert_eq!(solve_with(&input, &facing(Direction::North)), Some((1002, 3)));
+        assert_eq!(solve_with(&input, &facing(Direction::West)), Some((2002, 3)));
+        assert_eq!(solve_with(&input, &facing(Direction::East)), Some((2, 3)));
+    }
 }
@@ -1,46 +1,277 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use num::{BigUint, One, Zero};
+
+// the towel patterns compiled into a trie, so all towels that fit at a position are found
+// in a single walk down from the root
+#[derive(Clone, Debug, Default)]
+pub struct TowelTrie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct TrieNode {
+    children: Vec<(u8, usize)>,
+    is_towel: bool,
+}
+
+impl TowelTrie {
+    pub fn new<S: AsRef<str>>(towels: &[S]) -> TowelTrie {
+        let mut trie = TowelTrie { nodes: vec![TrieNode::default()] };
+        for towel in towels {
+            let mut node = 0;
+            for &b in towel.as_ref().as_bytes() {
+                node = match trie.child(node, b) {
+                    Some(next) => next,
+                    None => {
+                        trie.nodes.push(TrieNode::default());
+                        let next = trie.nodes.len() - 1;
+                        trie.nodes[node].children.push((b, next));
+                        next
+                    }
+                };
+            }
+            trie.nodes[node].is_towel = true;
+        }
+        trie
+    }
+
+    fn child(&self, node: usize, b: u8) -> Option<usize> {
+        self.nodes[node].children.iter().find(|&&(c, _)| c == b).map(|&(_, next)| next)
+    }
+
+    // the lengths of all towels that match the design at `start`, shortest first
+    pub fn towels_at<'a>(&'a self, design: &'a [u8], start: usize) -> impl Iterator<Item = usize> + 'a {
+        design[start..]
+            .iter()
+            .scan(0, move |node, &b| {
+                *node = self.child(*node, b)?;
+                Some(*node)
+            })
+            .enumerate()
+            .filter(move |&(_, node)| self.nodes[node].is_towel)
+            .map(|(i, _)| i + 1)
+    }
+
+    // how many characters from `start` on still follow some path in the trie
+    fn walk_len(&self, design: &[u8], start: usize) -> usize {
+        let mut node = 0;
+        design[start..].iter().take_while(|&&b| self.child(node, b).map(|next| node = next).is_some()).count()
+    }
+
+    // can_finish[i] tells whether design[i..] can be made of towels
+    fn can_finish(&self, design: &[u8]) -> Vec<bool> {
+        let n = design.len();
+        let mut can_finish = vec![false; n + 1];
+        can_finish[n] = true;
+        for i in (0..n).rev() {
+            can_finish[i] = self.towels_at(design, i).any(|len| can_finish[i + len]);
+        }
+        can_finish
+    }
+}
+
+// lazily walks all arrangements of a design depth first, shortest towels first;
+// dead ends are cut off up front so every step moves towards the next arrangement
+pub struct Arrangements<'a> {
+    design: &'a str,
+    towels: &'a TowelTrie,
+    can_finish: Vec<bool>,
+    // position in the design and the towel lengths still to try there, shortest last
+    stack: Vec<(usize, Vec<usize>)>,
+    path: Vec<usize>,
+}
+
+impl<'a> Arrangements<'a> {
+    pub fn new(design: &'a str, towels: &'a TowelTrie) -> Arrangements<'a> {
+        let can_finish = towels.can_finish(design.as_bytes());
+        let mut arrangements = Arrangements { design, towels, can_finish, stack: Vec::new(), path: Vec::new() };
+        if arrangements.can_finish[0] {
+            let candidates = arrangements.candidates(0);
+            arrangements.stack.push((0, candidates));
+        }
+        arrangements
+    }
+
+    fn candidates(&self, pos: usize) -> Vec<usize> {
+        let mut lens = self
+            .towels
+            .towels_at(self.design.as_bytes(), pos)
+            .filter(|&len| self.can_finish[pos + len])
+            .collect::<Vec<_>>();
+        lens.reverse();
+        lens
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pos, candidates) = self.stack.last_mut()?;
+            let pos = *pos;
+            if pos == self.design.len() {
+                let mut start = 0;
+                let pieces = self
+                    .path
+                    .iter()
+                    .map(|&len| {
+                        start += len;
+                        &self.design[start - len..start]
+                    })
+                    .collect();
+                self.stack.pop();
+                self.path.pop();
+                return Some(pieces);
+            }
+            match candidates.pop() {
+                Some(len) => {
+                    self.path.push(len);
+                    let next = self.candidates(pos + len);
+                    self.stack.push((pos + len, next));
+                }
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                }
+            }
+        }
+    }
+}
+
+// the arrangement using the fewest towels, `None` if the design is impossible
+pub fn fewest_towels<'a>(design: &'a str, towels: &TowelTrie) -> Option<Vec<&'a str>> {
+    let bytes = design.as_bytes();
+    let n = bytes.len();
+    // best[i] is the fewest towels covering design[..i] and the length of the last one
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; n + 1];
+    best[0] = Some((0, 0));
+    for i in 0..n {
+        let Some((count, _)) = best[i] else {
+            continue;
+        };
+        for len in towels.towels_at(bytes, i) {
+            if best[i + len].is_none_or(|(c, _)| count + 1 < c) {
+                best[i + len] = Some((count + 1, len));
+            }
+        }
+    }
+    best[n]?;
+    let mut pieces = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let (_, len) = best[end]?;
+        pieces.push(&design[end - len..end]);
+        end -= len;
+    }
+    pieces.reverse();
+    Some(pieces)
+}
+
+// why a design can not be made: `prefix_len` is the longest prefix that can be arranged and
+// `fail_pos` the first character no towel reaches when starting from any arrangeable prefix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchFailure {
+    pub prefix_len: usize,
+    pub fail_pos: usize,
+}
+
+pub fn diagnose(design: &str, towels: &TowelTrie) -> Result<(), MatchFailure> {
+    let bytes = design.as_bytes();
+    let n = bytes.len();
+    let mut reachable = vec![false; n + 1];
+    reachable[0] = true;
+    let mut fail_pos = 0;
+    for i in 0..n {
+        if !reachable[i] {
+            continue;
+        }
+        fail_pos = fail_pos.max(i + towels.walk_len(bytes, i));
+        for len in towels.towels_at(bytes, i) {
+            reachable[i + len] = true;
+        }
+    }
+    if reachable[n] {
+        return Ok(());
+    }
+    let prefix_len = (0..=n).rev().find(|&i| reachable[i]).unwrap_or(0);
+    Err(MatchFailure { prefix_len, fail_pos })
+}
 
 #[derive(Clone, Debug)]
 pub struct Input {
-    tokens: Vec<String>,
+    towels: TowelTrie,
     patterns: Vec<String>,
 }
 
 #[aoc_generator(day19)]
 fn read_inputs(input: &str) -> Option<Input> {
     let mut iter = input.split("\n\n");
-    let first = iter.next().unwrap().split(", ").map(|s| s.to_string()).collect();
-    let second = iter.next().unwrap().lines().map(|x| x.to_string()).collect();
-    Some(Input{tokens: first, 
+    let first = iter.next()?.split(", ").collect::<Vec<_>>();
+    let second = iter.next()?.lines().map(|x| x.to_string()).collect();
+    Some(Input{towels: TowelTrie::new(&first),
             patterns: second})
 }
 
-fn count_tokenization(text: &str, patterns: &Vec<String>) -> u64 {
+// forward DP over design positions; `add` folds the ways into a position into the ways out
+// of it and may give up (on overflow) by returning `None`. Only positions that can still reach
+// the end are counted, so no partial count is larger than the total and `add` only gives up
+// when the total would not fit either. An impossible design also ends up as `None`.
+fn count_with<T: Clone>(text: &str, towels: &TowelTrie, one: T, add: impl Fn(&T, &T) -> Option<T>) -> Option<T> {
+    let text = text.as_bytes();
     let n = text.len();
-    let mut dp = vec![0; n + 1];
-    dp[0] = 1;
-
-    for i in 1..n + 1 {
-        for j in 0..=i {
-            if dp[j] > 0 && patterns.contains(&text[j..i].to_string()) {
-                dp[i] += dp[j];
+    let can_finish = towels.can_finish(text);
+    let mut dp: Vec<Option<T>> = vec![None; n + 1];
+    dp[0] = Some(one);
 
-            }
+    for i in 0..n {
+        let Some(ways) = dp[i].take() else {
+            continue;
+        };
+        for len in towels.towels_at(text, i).filter(|&len| can_finish[i + len]) {
+            dp[i + len] = Some(match &dp[i + len] {
+                Some(other) => add(other, &ways)?,
+                None => ways.clone(),
+            });
         }
     }
-    dp[n]
+    dp[n].take()
+}
+
+// `Some(0)` for an impossible design, `None` if the count does not fit in a u64
+fn count_tokenization(text: &str, towels: &TowelTrie) -> Option<u64> {
+    if !can_tokenize(text, towels) {
+        return Some(0);
+    }
+    count_with(text, towels, 1u64, |a, b| a.checked_add(*b))
+}
+
+// the exact number of arrangements, only switching to big integers once a u64 overflows
+pub fn count_arrangements(text: &str, towels: &TowelTrie) -> BigUint {
+    match count_tokenization(text, towels) {
+        Some(ways) => BigUint::from(ways),
+        None => count_with(text, towels, BigUint::one(), |a, b| Some(a + b)).unwrap_or_else(BigUint::zero),
+    }
 }
 
-fn can_tokenize(text: &str, patterns: &Vec<String>) -> bool {
+// the number of arrangements modulo `prime`, which must not be zero
+pub fn count_arrangements_mod(text: &str, towels: &TowelTrie, prime: u64) -> u64 {
+    let prime = prime as u128;
+    count_with(text, towels, 1 % prime, |a, b| Some((a + b) % prime)).unwrap_or(0) as u64
+}
+
+fn can_tokenize(text: &str, towels: &TowelTrie) -> bool {
+    let text = text.as_bytes();
     let n = text.len();
     let mut dp = vec![false; n + 1];
     dp[0] = true;
 
-    for i in 1..n + 1 {
-        for j in 0..=i {
-            if dp[j] && patterns.contains(&text[j..i].to_string()) {
-                dp[i] = true;
-            }
+    for i in 0..n {
+        if !dp[i] {
+            continue;
+        }
+        for len in towels.towels_at(text, i) {
+            dp[i + len] = true;
         }
     }
     dp[n]
@@ -50,7 +281,7 @@ fn can_tokenize(text: &str, patterns: &Vec<String>) -> bool {
 fn part_1 (inp: &Input) -> u64 {
     let mut possible  = Vec::new();
     for stripe in &inp.patterns {
-        if can_tokenize(&stripe, &inp.tokens) {
+        if can_tokenize(stripe, &inp.towels) {
             possible.push(stripe);
         }
     }
@@ -59,19 +290,19 @@ fn part_1 (inp: &Input) -> u64 {
 }
 
 #[aoc(day19, part2)]
-fn part_2 (inp: &Input) -> u64 {
+fn part_2 (inp: &Input) -> BigUint {
     let mut possible  = Vec::new();
     for stripe in &inp.patterns {
-        if can_tokenize(&stripe, &inp.tokens) {
+        if can_tokenize(stripe, &inp.towels) {
             possible.push(stripe);
         }
     }
     let mut sums = Vec::new();
     for towel in possible {
-        let sum = count_tokenization(&towel, &inp.tokens);
+        let sum = count_arrangements(towel, &inp.towels);
         sums.push(sum);
     }
-    sums.iter().sum::<u64>()
+    sums.iter().sum::<BigUint>()
 }
 
 #[cfg(test)]
@@ -100,6 +331,79 @@ mod tests {
     fn test_p2() {
         let inp  = read_inputs(TEST_INPUT).expect("valid input");
         let res = part_2(&inp);
-        assert_eq!(res, 16);
+        assert_eq!(res, BigUint::from(16u32));
+    }
+
+    #[test]
+    fn test_trie() {
+        let trie = TowelTrie::new(&["r", "wr", "b", "g", "bwu", "rb", "gb", "br"]);
+        assert_eq!(trie.towels_at(b"brwrr", 0).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(trie.towels_at(b"brwrr", 2).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(trie.towels_at(b"ubwu", 0).count(), 0);
+        assert_eq!(count_tokenization("gbbr", &trie), Some(4));
+    }
+
+    #[test]
+    fn test_arrangements() {
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
+        let all = Arrangements::new("gbbr", &inp.towels).collect::<Vec<_>>();
+        assert_eq!(all, vec![
+            vec!["g", "b", "b", "r"],
+            vec!["g", "b", "br"],
+            vec!["gb", "b", "r"],
+            vec!["gb", "br"],
+        ]);
+        assert_eq!(Arrangements::new("rrbgbr", &inp.towels).count(), 6);
+        assert_eq!(Arrangements::new("ubwu", &inp.towels).next(), None);
+        let towels = TowelTrie::new(&["w", "ww"]);
+        assert_eq!(Arrangements::new(&"w".repeat(200), &towels).take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_fewest_towels() {
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
+        assert_eq!(fewest_towels("gbbr", &inp.towels), Some(vec!["gb", "br"]));
+        assert_eq!(fewest_towels("bwurrg", &inp.towels), Some(vec!["bwu", "r", "r", "g"]));
+        assert_eq!(fewest_towels("bbrgwb", &inp.towels), None);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let inp = read_inputs(TEST_INPUT).expect("valid input");
+        assert_eq!(diagnose("brwrr", &inp.towels), Ok(()));
+        assert_eq!(diagnose("ubwu", &inp.towels), Err(MatchFailure { prefix_len: 0, fail_pos: 0 }));
+        // "bbrg" can be arranged, the "w" after it only starts "wr"
+        assert_eq!(diagnose("bbrgwb", &inp.towels), Err(MatchFailure { prefix_len: 4, fail_pos: 5 }));
+    }
+
+    #[test]
+    fn test_long_design() {
+        let towels = (1..=8).map(|n| "w".repeat(n)).collect::<Vec<_>>();
+        let trie = TowelTrie::new(&towels);
+        let design = "w".repeat(5000) + "u";
+        assert!(!can_tokenize(&design, &trie));
+        assert!(can_tokenize(&design[..5000], &trie));
+        assert_eq!(count_tokenization(&"w".repeat(10), &trie), Some(509));
+        assert_eq!(count_tokenization(&design, &trie), Some(0));
+    }
+
+    #[test]
+    fn test_big_counts() {
+        // with towels "w" and "ww" the arrangements of n stripes are the Fibonacci number F(n + 1)
+        let trie = TowelTrie::new(&["w", "ww"]);
+        let fib = |n: usize| (0..n).fold((BigUint::zero(), BigUint::one()), |(a, b), _| (b.clone(), a + b)).0;
+        assert_eq!(count_tokenization(&"w".repeat(92), &trie), Some(12200160415121876738));
+        assert_eq!(count_tokenization(&"w".repeat(100), &trie), None);
+        assert_eq!(count_arrangements(&"w".repeat(92), &trie), fib(93));
+        assert_eq!(count_arrangements(&"w".repeat(300), &trie), fib(301));
+        assert_eq!(count_arrangements(&("w".repeat(300) + "u"), &trie), BigUint::zero());
+        let prime = 1_000_000_007u64;
+        let expected = fib(301) % BigUint::from(prime);
+        assert_eq!(BigUint::from(count_arrangements_mod(&"w".repeat(300), &trie, prime)), expected);
+        assert_eq!(count_arrangements_mod("u", &trie, prime), 0);
+        // the prefix counts overflow, but only one towel covers the whole design
+        let whole = "w".repeat(100) + "u";
+        let trie = TowelTrie::new(&["w".to_string(), "ww".to_string(), whole.clone()]);
+        assert_eq!(count_tokenization(&whole, &trie), Some(1));
     }
 }
\ No newline at end of file
@@ -1,40 +1,29 @@
-use std::{collections::HashMap, iter::zip, ops::BitXor};
-use memoize::memoize;
+use rayon::prelude::*;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+const MASK: u32 = (1 << 24) - 1;
+// a price change lies in -9..=9, so a window of four changes is a 4 digit base 19 number
+const NUM_WINDOWS: usize = 19 * 19 * 19 * 19;
+
 #[aoc_generator(day22)]
-pub fn input_generator(input: &str) -> Vec<i128> {
+pub fn input_generator(input: &str) -> Vec<u32> {
     input
         .split("\n")
         .map(|l| l.parse().unwrap())
         .collect()
 }
 
-#[memoize]
-fn get_next_secret_number(secret_number: i128) -> i128 {
-    
-    // step 1
-    let mut result = secret_number * 64;
+// multiplying by 64 / 2048 and dividing by 32 are shifts, the modulo 16777216 is a 24 bit mask
+fn get_next_secret_number(secret_number: u32) -> u32 {
     let mut new_secret = secret_number;
-    new_secret = new_secret.bitxor(result);
-    new_secret %= 16777216;
-
-    // step 2
-    result = new_secret / 32;
-    new_secret = new_secret.bitxor(result);
-    new_secret %= 16777216;
-
-    // step 3
-    result = new_secret * 2048;
-    new_secret = new_secret.bitxor(result);
-    new_secret %= 16777216;
-
+    new_secret ^= (new_secret << 6) & MASK;
+    new_secret ^= new_secret >> 5;
+    new_secret ^= (new_secret << 11) & MASK;
     new_secret
-    }
-
+}
 
-fn get_i_iterations(secret_number: i128, i: isize) -> i128 {
+fn get_i_iterations(secret_number: u32, i: usize) -> u32 {
     let mut new_secret = secret_number;
     for _ in 0..i {
         new_secret = get_next_secret_number(new_secret);
@@ -42,66 +31,257 @@ fn get_i_iterations(secret_number: i128, i: isize) -> i128 {
     new_secret
 }
 
-fn get_i_iteration_last_digit(secret_number: i128, i: isize) -> Vec<i32> {
+fn get_i_iteration_last_digit(secret_number: u32, i: usize) -> Vec<u8> {
     let mut new_secret = secret_number;
-    let mut last_digits = Vec::new();
-    last_digits.push((new_secret % 10) as i32);
+    let mut last_digits = Vec::with_capacity(i + 1);
+    last_digits.push((new_secret % 10) as u8);
     for _ in 0..i {
         new_secret = get_next_secret_number(new_secret);
-        last_digits.push((new_secret % 10) as i32);
-
+        last_digits.push((new_secret % 10) as u8);
     }
     last_digits
 }
 
-fn get_diff(last_digits: &Vec<i32>) -> Vec<i32> {
-    last_digits.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>()
+fn next_window(window: usize, prev: u8, price: u8) -> usize {
+    (window * 19 + (price + 9 - prev) as usize) % NUM_WINDOWS
+}
+
+// adds the price of the first sale for every change window seen by one buyer;
+// `seen` holds the stamp of the last buyer that visited a window, so it never needs clearing
+fn add_buyer_prices(prices: &[u8], stamp: u32, totals: &mut [u32], seen: &mut [u32]) {
+    let mut window = 0;
+    for (i, pair) in prices.windows(2).enumerate() {
+        window = next_window(window, pair[0], pair[1]);
+        if i >= 3 && seen[window] != stamp {
+            seen[window] = stamp;
+            totals[window] += pair[1] as u32;
+        }
+    }
+}
+
+fn get_window_totals(secret_numbers: &[u32], iterations: usize) -> Vec<u32> {
+    secret_numbers
+        .par_iter()
+        .enumerate()
+        .fold(
+            || (vec![0; NUM_WINDOWS], vec![0; NUM_WINDOWS]),
+            |(mut totals, mut seen), (i, &secret)| {
+                let prices = get_i_iteration_last_digit(secret, iterations);
+                add_buyer_prices(&prices, i as u32 + 1, &mut totals, &mut seen);
+                (totals, seen)
+            },
+        )
+        .map(|(totals, _)| totals)
+        .reduce(
+            || vec![0; NUM_WINDOWS],
+            |mut acc, totals| {
+                acc.iter_mut().zip(totals).for_each(|(a, t)| *a += t);
+                acc
+            },
+        )
+}
+
+fn get_best_sequence(secret_numbers: &[u32]) -> (Vec<u32>, u32) {
+    let totals = get_window_totals(secret_numbers, 2000);
+    let max_value = *totals.iter().max().unwrap();
+    (totals, max_value)
 }
 
+pub type ChangeSequence = [i8; 4];
 
-fn get_last_digits(sec_nums: &Vec<i128>, i: isize) -> Vec<Vec<i32>> {
-    sec_nums.iter().map(|sn| get_i_iteration_last_digit(*sn, i)).collect::<Vec<_>>()
+// prices are single digits, so every change lies in -9..=9; `None` for a sequence outside that range
+pub fn encode_sequence(sequence: ChangeSequence) -> Option<usize> {
+    sequence.iter().try_fold(0, |window, &d| {
+        (-9..=9).contains(&d).then(|| window * 19 + (d as i16 + 9) as usize)
+    })
 }
 
-fn get_diffs(last_digits: &Vec<Vec<i32>>) -> Vec<Vec<i32>> {
-    last_digits.iter().map(|ld| get_diff(ld)).collect::<Vec<_>>()
+pub fn decode_sequence(mut window: usize) -> ChangeSequence {
+    let mut sequence = [0; 4];
+    for d in sequence.iter_mut().rev() {
+        *d = (window % 19) as i8 - 9;
+        window /= 19;
+    }
+    sequence
+}
+
+// the sequences with the most bananas, best first; ties keep the lower encoded sequence first
+pub fn top_sequences(secret_numbers: &[u32], n: usize) -> Vec<(ChangeSequence, u32)> {
+    let totals = get_window_totals(secret_numbers, 2000);
+    let mut ranked = (0..NUM_WINDOWS)
+        .filter(|&w| totals[w] > 0)
+        .collect::<Vec<_>>();
+    ranked.sort_by_key(|&w| std::cmp::Reverse(totals[w]));
+    ranked
+        .into_iter()
+        .take(n)
+        .map(|w| (decode_sequence(w), totals[w]))
+        .collect()
 }
-    
 
-fn get_best_sequence(secret_numbers: &Vec<i128>) -> (HashMap<Vec<i32>, i32>, i32) {
-    let mut best_changes =HashMap::new();
-    
-    let ld = get_last_digits(secret_numbers, 2000);
-    let diffs = get_diffs(&ld);
-    for (digits, diff) in zip(ld, diffs) {
-        let mut visited_seqs = HashMap::new();
-        for i in 0..diff.len() - 4 {
-            let seq = diff[i..i+4].to_vec();
-            if visited_seqs.contains_key(&seq) {
-                continue;
-            } else {
-                visited_seqs.insert(seq.clone(), 1);
-                let score = digits[i+4];
-                let count = best_changes.entry(seq).or_insert(0);
-                *count += score;
+pub fn best_sequence(secret_numbers: &[u32]) -> Option<(ChangeSequence, u32)> {
+    top_sequences(secret_numbers, 1).pop()
+}
+
+// the price each buyer sells at for the sequence, `None` if the sequence never shows up for them;
+// the whole result is `None` if the sequence has a change no price difference can have
+pub fn sale_prices(secret_numbers: &[u32], sequence: ChangeSequence) -> Option<Vec<Option<u8>>> {
+    let target = encode_sequence(sequence)?;
+    let sold = secret_numbers
+        .par_iter()
+        .map(|&secret| {
+            let prices = get_i_iteration_last_digit(secret, 2000);
+            let mut window = 0;
+            prices.windows(2).enumerate().find_map(|(i, pair)| {
+                window = next_window(window, pair[0], pair[1]);
+                (i >= 3 && window == target).then_some(pair[1])
+            })
+        })
+        .collect();
+    Some(sold)
+}
+
+// every step is a xor of shifted copies of the secret, so it is a linear map on the 24 bit vector space over GF(2);
+// column `j` of the matrix holds the image of the secret with only bit `j` set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitMatrix([u32; 24]);
+
+impl BitMatrix {
+    pub fn identity() -> BitMatrix {
+        let mut columns = [0; 24];
+        for (j, col) in columns.iter_mut().enumerate() {
+            *col = 1 << j;
+        }
+        BitMatrix(columns)
+    }
+
+    pub fn secret_step() -> BitMatrix {
+        let mut columns = [0; 24];
+        for (j, col) in columns.iter_mut().enumerate() {
+            *col = get_next_secret_number(1 << j);
+        }
+        BitMatrix(columns)
+    }
+
+    pub fn apply(&self, vector: u32) -> u32 {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| vector >> j & 1 == 1)
+            .fold(0, |acc, (_, col)| acc ^ col)
+    }
+
+    // the matrix of applying `other` first and then `self`
+    pub fn compose(&self, other: &BitMatrix) -> BitMatrix {
+        let mut columns = [0; 24];
+        for (col, other_col) in columns.iter_mut().zip(other.0) {
+            *col = self.apply(other_col);
+        }
+        BitMatrix(columns)
+    }
+
+    pub fn pow(&self, mut exponent: u64) -> BitMatrix {
+        let mut result = BitMatrix::identity();
+        let mut base = *self;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.compose(&base);
             }
+            base = base.compose(&base);
+            exponent >>= 1;
         }
+        result
     }
-    let max_value = *best_changes.values().max().unwrap();
-    (best_changes, max_value)
 }
 
-#[aoc(day22, part1)]
-fn part1(input: &Vec<i128>) -> i128 {
-    let mut final_sum = 0;
-    for it in input {
-        final_sum += get_i_iterations(*it, 2000);
+pub fn secret_after(secret_number: u32, iterations: u64) -> u32 {
+    BitMatrix::secret_step().pow(iterations).apply(secret_number)
+}
+
+fn prime_factors(mut n: u64) -> Vec<u64> {
+    let mut factors = Vec::new();
+    let mut p = 2;
+    while p * p <= n {
+        while n.is_multiple_of(p) {
+            factors.push(p);
+            n /= p;
+        }
+        p += 1;
+    }
+    if n > 1 {
+        factors.push(n);
+    }
+    factors
+}
+
+// the order of any invertible 24x24 matrix over GF(2) divides lcm(2^k - 1 for k in 1..=24) * 2^5,
+// kept as (prime, exponent) pairs since the number itself does not fit in 128 bits
+fn group_exponent() -> Vec<(u64, u32)> {
+    let mut exponents: Vec<(u64, u32)> = vec![(2, 5)];
+    for k in 1..=24 {
+        let mut counts: Vec<(u64, u32)> = Vec::new();
+        for p in prime_factors((1 << k) - 1) {
+            match counts.iter_mut().find(|(q, _)| *q == p) {
+                Some((_, e)) => *e += 1,
+                None => counts.push((p, 1)),
+            }
+        }
+        for (p, e) in counts {
+            match exponents.iter_mut().find(|(q, _)| *q == p) {
+                Some((_, max_e)) => *max_e = (*max_e).max(e),
+                None => exponents.push((p, e)),
+            }
+        }
     }
-    final_sum
+    exponents
+}
+
+fn pow_factored(matrix: &BitMatrix, factors: &[(u64, u32)]) -> BitMatrix {
+    let mut result = *matrix;
+    for &(p, e) in factors {
+        for _ in 0..e {
+            result = result.pow(p);
+        }
+    }
+    result
+}
+
+// strips prime factors off the group exponent as long as `is_identity` still holds,
+// which leaves the smallest `n` with `is_identity(step^n)`
+fn smallest_period(is_identity: impl Fn(&BitMatrix) -> bool) -> u64 {
+    let step = BitMatrix::secret_step();
+    let mut factors = group_exponent();
+    for i in 0..factors.len() {
+        while factors[i].1 > 0 {
+            factors[i].1 -= 1;
+            if !is_identity(&pow_factored(&step, &factors)) {
+                factors[i].1 += 1;
+                break;
+            }
+        }
+    }
+    factors.iter().map(|&(p, e)| p.pow(e)).product()
+}
+
+// the order of the step matrix: every secret repeats after this many steps
+pub fn generator_cycle_length() -> u64 {
+    smallest_period(|m| *m == BitMatrix::identity())
+}
+
+pub fn secret_cycle_length(secret_number: u32) -> u64 {
+    smallest_period(|m| m.apply(secret_number) == secret_number)
+}
+
+#[aoc(day22, part1)]
+fn part1(input: &[u32]) -> u64 {
+    input
+        .par_iter()
+        .map(|&secret| get_i_iterations(secret, 2000) as u64)
+        .sum()
 }
 
 #[aoc(day22, part2)]
-fn part2(input: &Vec<i128>) -> i32 {
+fn part2(input: &[u32]) -> u32 {
     let (_, max_value) = get_best_sequence(input);
     max_value
 }
@@ -153,5 +333,40 @@ mod tests {
         assert_eq!(score, 23);
     }
 
+    #[test]
+    fn sample7() {
+        // -2,1,-1,3 is the winning sequence from the puzzle text
+        let inp = input_generator(SAMPLE2);
+        let (totals, _) = get_best_sequence(&inp);
+        assert_eq!(totals[encode_sequence([-2, 1, -1, 3]).unwrap()], 23);
+    }
 
+    #[test]
+    fn sample8() {
+        let inp = input_generator(SAMPLE2);
+        assert_eq!(encode_sequence([-2, 1, -1, 3]).map(decode_sequence), Some([-2, 1, -1, 3]));
+        assert_eq!(encode_sequence([-9, 9, 0, 0]).map(decode_sequence), Some([-9, 9, 0, 0]));
+        assert_eq!(encode_sequence([-2, 1, 10, 3]), None);
+        assert_eq!(encode_sequence([i8::MAX, 0, 0, 0]), None);
+        assert_eq!(sale_prices(&inp, [-10, 1, -1, 3]), None);
+        assert_eq!(best_sequence(&inp), Some(([-2, 1, -1, 3], 23)));
+        assert_eq!(top_sequences(&inp, 3).len(), 3);
+        assert_eq!(sale_prices(&inp, [-2, 1, -1, 3]), Some(vec![Some(7), Some(7), None, Some(9)]));
+    }
+
+    #[test]
+    fn sample9() {
+        assert_eq!(secret_after(123, 1), 15887950);
+        assert_eq!(secret_after(123, 10), get_i_iterations(123, 10));
+        assert_eq!(secret_after(2024, 2000), 8667524);
+    }
+
+    #[test]
+    fn sample10() {
+        let cycle = generator_cycle_length();
+        assert_eq!(cycle, 16777215);
+        assert_eq!(secret_cycle_length(0), 1);
+        assert_eq!(secret_cycle_length(123), cycle);
+        assert_eq!(secret_after(123, 1_000_000_000_000), secret_after(123, 1_000_000_000_000 % cycle));
+    }
 }
@@ -1,70 +1,270 @@
+use std::{collections::HashMap, fmt};
+
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::iproduct;
 
-struct Lock {
-    pins: Vec<u16>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Lock,
+    Key,
+}
+
+// `height` is the room for pins between the solid top and bottom rows of the schematic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    pub kind: Kind,
+    pub pins: Vec<u16>,
+    pub height: u16,
+}
+pub struct Input {
+    pub keys: Vec<Lock>,
+    pub locks: Vec<Lock>,
+}
+
+// `block` counts the schematics from 0 and `row` the lines within one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchematicError {
+    TooShort { block: usize },
+    InvalidChar { block: usize, row: usize, c: char },
+    NotRectangular { block: usize, row: usize },
+    SizeMismatch { block: usize, expected: (usize, usize), found: (usize, usize) },
+    NoSolidRow { block: usize },
+    BrokenPin { block: usize, column: usize },
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchematicError::TooShort { block } => write!(f, "schematic {} needs at least a top and a bottom row", block),
+            SchematicError::InvalidChar { block, row, c } => write!(f, "invalid character {:?} in row {} of schematic {}", c, row, block),
+            SchematicError::NotRectangular { block, row } => write!(f, "row {} of schematic {} has a different width than its first row", row, block),
+            SchematicError::SizeMismatch { block, expected, found } => write!(
+                f,
+                "schematic {} is {}x{}, expected {}x{} like the first one",
+                block, found.0, found.1, expected.0, expected.1
+            ),
+            SchematicError::NoSolidRow { block } => write!(f, "schematic {} needs a row of '#' on top (lock) or at the bottom (key) and a row of '.' opposite it", block),
+            SchematicError::BrokenPin { block, column } => write!(f, "pin in column {} of schematic {} has a gap", column, block),
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {}
+
+impl Lock {
+    // a lock hangs from a solid top row and a key stands on a solid bottom row, every pin column
+    // has to be a single run of '#' starting at that solid row
+    fn parse(block: usize, schematic: &str) -> Result<Lock, SchematicError> {
+        let mut rows = Vec::new();
+        for (row, line) in schematic.lines().enumerate() {
+            let cells = line
+                .chars()
+                .map(|c| match c {
+                    '#' => Ok(true),
+                    '.' => Ok(false),
+                    c => Err(SchematicError::InvalidChar { block, row, c }),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push(cells);
+        }
+        if rows.len() < 2 {
+            return Err(SchematicError::TooShort { block });
+        }
+        let width = rows[0].len();
+        if let Some(row) = rows.iter().position(|r| r.len() != width) {
+            return Err(SchematicError::NotRectangular { block, row });
+        }
+        let solid = |row: &[bool], filled: bool| row.iter().all(|&c| c == filled);
+        let (first, last) = (&rows[0], &rows[rows.len() - 1]);
+        let kind = if solid(first, true) && solid(last, false) {
+            Kind::Lock
+        } else if solid(first, false) && solid(last, true) {
+            Kind::Key
+        } else {
+            return Err(SchematicError::NoSolidRow { block });
+        };
+
+        let mut inner = rows[1..rows.len() - 1].to_vec();
+        if kind == Kind::Key {
+            inner.reverse();
+        }
+        let mut pins = Vec::with_capacity(width);
+        for column in 0..width {
+            let pin = inner.iter().take_while(|row| row[column]).count();
+            if inner[pin..].iter().any(|row| row[column]) {
+                return Err(SchematicError::BrokenPin { block, column });
+            }
+            pins.push(pin as u16);
+        }
+        Ok(Lock { kind, pins, height: inner.len() as u16 })
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.pins.len(), self.height as usize + 2)
+    }
 }
-struct Input {
-    keys: Vec<Lock>,
-    locks: Vec<Lock>,
+
+// the schematic the lock or key was read from
+impl fmt::Display for Lock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, rows) = self.size();
+        for row in 0..rows {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            // how far the row is from the solid row the pins grow out of
+            let depth = match self.kind {
+                Kind::Lock => row,
+                Kind::Key => rows - 1 - row,
+            };
+            for column in 0..width {
+                let filled = depth == 0 || (depth <= self.height as usize && depth <= self.pins[column] as usize);
+                write!(f, "{}", if filled { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
 }
 
+// every schematic has to have the size of the first one
 #[aoc_generator(day25)]
-fn read_input(input: &str) -> Input {
+pub fn read_input(input: &str) -> Result<Input, SchematicError> {
+    let input = input.replace("\r\n", "\n");
     let mut keys = Vec::new();
     let mut locks = Vec::new();
-    let mut key_flag;
-    let chunks = input.split("\n\n").collect::<Vec<&str>>();
-    for chunk in chunks {
-        let lines = chunk.lines().collect::<Vec<&str>>();
-        if lines[0].chars().all(|c| c == '.') {
-            key_flag = true;
+    let mut size = None;
+    for (block, chunk) in input.trim_end().split("\n\n").enumerate() {
+        let schematic = Lock::parse(block, chunk)?;
+        let expected = *size.get_or_insert(schematic.size());
+        if schematic.size() != expected {
+            return Err(SchematicError::SizeMismatch { block, expected, found: schematic.size() });
         }
-        else {
-            key_flag = false;
+        match schematic.kind {
+            Kind::Key => keys.push(schematic),
+            Kind::Lock => locks.push(schematic),
+        }
+    }
+
+    Ok(Input { keys, locks })
+}
+
+// a key only fits a lock made for the same height
+fn check_pins(key: &Lock, lock: &Lock) -> bool {
+    key.height == lock.height && key.pins.iter().zip(lock.pins.iter()).all(|(k, l)| k + l <= lock.height)
+}
+
+// keys grouped by pin profile, with a bitset of profiles for every column and pin limit so the
+// keys fitting a lock come from a few bitset intersections instead of trying every key
+pub struct KeyIndex {
+    keys: Vec<Vec<usize>>,
+    // at_most[column][v] holds the profiles whose pin in that column is at most v
+    at_most: Vec<Vec<Vec<u64>>>,
+    by_height: HashMap<u16, Vec<u64>>,
+}
+
+impl KeyIndex {
+    pub fn new(keys: &[Lock]) -> KeyIndex {
+        let mut ids: HashMap<(u16, &[u16]), usize> = HashMap::new();
+        let mut profiles: Vec<(u16, &[u16])> = Vec::new();
+        let mut grouped: Vec<Vec<usize>> = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let profile = (key.height, key.pins.as_slice());
+            let id = *ids.entry(profile).or_insert_with(|| {
+                profiles.push(profile);
+                grouped.push(Vec::new());
+                profiles.len() - 1
+            });
+            grouped[id].push(i);
         }
-        let mut pins = vec![0; lines[0].len()];
-        for line in lines[1..lines.len()-1].iter() {
-            
-            for (i, c) in line.chars().enumerate() {
-                if c == '#' {
-                    pins[i] += 1;
+
+        let words = profiles.len().div_ceil(64);
+        let width = profiles.iter().map(|(_, pins)| pins.len()).max().unwrap_or(0);
+        let max_pin = profiles.iter().flat_map(|(_, pins)| pins.iter()).copied().max().unwrap_or(0) as usize;
+        let mut at_most = vec![vec![vec![0u64; words]; max_pin + 1]; width];
+        let mut by_height: HashMap<u16, Vec<u64>> = HashMap::new();
+        for (id, (height, pins)) in profiles.iter().enumerate() {
+            let (word, bit) = (id / 64, 1u64 << (id % 64));
+            by_height.entry(*height).or_insert_with(|| vec![0; words])[word] |= bit;
+            for (column, limits) in at_most.iter_mut().enumerate() {
+                let pin = pins.get(column).copied().unwrap_or(0) as usize;
+                for set in &mut limits[pin..] {
+                    set[word] |= bit;
                 }
             }
         }
-        if key_flag {
-            keys.push(Lock { pins });
+        KeyIndex { keys: grouped, at_most, by_height }
+    }
+
+    // indices of the keys that fit the lock, in increasing order
+    pub fn fitting_keys(&self, lock: &Lock) -> Vec<usize> {
+        let Some(mut mask) = self.by_height.get(&lock.height).cloned() else {
+            return Vec::new();
+        };
+        for (limits, &pin) in self.at_most.iter().zip(lock.pins.iter()) {
+            let Some(limit) = lock.height.checked_sub(pin) else {
+                return Vec::new();
+            };
+            let set = &limits[(limit as usize).min(limits.len() - 1)];
+            mask.iter_mut().zip(set).for_each(|(m, s)| *m &= s);
         }
-        else {
-            locks.push(Lock { pins });
-        }        
-    }  
+        let mut keys = mask
+            .iter()
+            .enumerate()
+            .flat_map(|(word, &bits)| (0..64).filter(move |b| bits >> b & 1 == 1).map(move |b| word * 64 + b))
+            .flat_map(|id| self.keys[id].iter().copied())
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+}
 
-    Input { keys, locks }
+// every (key, lock) index pair that fits, ordered by lock and then key
+pub fn fitting_pairs(inp: &Input) -> Vec<(usize, usize)> {
+    let index = KeyIndex::new(&inp.keys);
+    inp.locks
+        .iter()
+        .enumerate()
+        .flat_map(|(l, lock)| index.fitting_keys(lock).into_iter().map(move |k| (k, l)))
+        .collect()
 }
 
-fn check_pins(key: &Lock, lock: &Lock) -> bool {
-    for (k, l) in key.pins.iter().zip(lock.pins.iter()) {
-        if k + l > 5 {
-            return false;
-        }
+// why a key does not fit a lock: the columns where the pins collide and by how many rows;
+// a key made for another height lists no columns
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlap {
+    pub key: usize,
+    pub lock: usize,
+    pub columns: Vec<(usize, u16)>,
+}
+
+pub fn overlap(key: &Lock, lock: &Lock) -> Vec<(usize, u16)> {
+    if key.height != lock.height {
+        return Vec::new();
     }
-    true
+    key.pins
+        .iter()
+        .zip(lock.pins.iter())
+        .enumerate()
+        .filter(|&(_, (k, l))| k + l > lock.height)
+        .map(|(column, (k, l))| (column, k + l - lock.height))
+        .collect()
 }
 
-fn check_all_locks(inp: &Input) -> usize {
-    let mut count = 0;
-    for (key, lock) in iproduct!(inp.keys.iter(), inp.locks.iter()) {
-        if check_pins(key, lock) {
-            count += 1;
+// an `Overlap` for every (key, lock) pair that does not fit, ordered by lock and then key
+pub fn overlaps(inp: &Input) -> Vec<Overlap> {
+    let mut found = Vec::new();
+    for (l, lock) in inp.locks.iter().enumerate() {
+        for (k, key) in inp.keys.iter().enumerate() {
+            if !check_pins(key, lock) {
+                found.push(Overlap { key: k, lock: l, columns: overlap(key, lock) });
+            }
         }
     }
-    count
+    found
 }
 
 #[aoc(day25, part1)]
 fn part1(inp: &Input) -> usize {
-    check_all_locks(inp)
+    fitting_pairs(inp).len()
 }
 
 #[cfg(test)]
@@ -114,15 +314,80 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let input = read_input(TEST_INPUT);
+        let input = read_input(TEST_INPUT).expect("valid input");
         let key = &input.keys[0];
         assert_eq!(key.pins, vec![5, 0, 2, 1, 3]);
     }
 
     #[test]
     fn test_part1() {
-        let input = read_input(TEST_INPUT);
+        let input = read_input(TEST_INPUT).expect("valid input");
         assert_eq!(part1(&input), 3);
     }
 
+    #[test]
+    fn test_fitting_pairs() {
+        let input = read_input(TEST_INPUT).expect("valid input");
+        assert_eq!(input.locks[0].height, 5);
+        assert_eq!(fitting_pairs(&input), vec![(2, 0), (1, 1), (2, 1)]);
+        let fits = fitting_pairs(&input);
+        for (k, key) in input.keys.iter().enumerate() {
+            for (l, lock) in input.locks.iter().enumerate() {
+                assert_eq!(check_pins(key, lock), fits.contains(&(k, l)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let input = read_input(TEST_INPUT).expect("valid input");
+        let found = overlaps(&input);
+        assert_eq!(found.len(), 3);
+        // lock 0,5,3,4,3 and key 5,0,2,1,3 collide in the last column
+        assert_eq!(found[0], Overlap { key: 0, lock: 0, columns: vec![(4, 1)] });
+        assert_eq!(found[2], Overlap { key: 0, lock: 1, columns: vec![(0, 1), (3, 1), (4, 1)] });
+    }
+
+    #[test]
+    fn test_height_mismatch() {
+        let input = Input {
+            keys: vec![Lock { kind: Kind::Key, pins: vec![6, 0], height: 6 }],
+            locks: vec![Lock { kind: Kind::Lock, pins: vec![1, 0], height: 5 }],
+        };
+        assert!(fitting_pairs(&input).is_empty());
+        assert_eq!(overlaps(&input), vec![Overlap { key: 0, lock: 0, columns: vec![] }]);
+    }
+
+    #[test]
+    fn test_taller_schematics() {
+        let input = read_input("#####\n#.###\n#..#.\n.....\n.....\n.....\n.....\n.....\n\n\
+                                .....\n.....\n.....\n.....\n....#\n..#.#\n.##.#\n#####").expect("valid input");
+        assert_eq!(input.locks[0].height, 6);
+        assert_eq!(input.keys[0].pins, vec![0, 1, 2, 0, 3]);
+        assert_eq!(fitting_pairs(&input), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_render() {
+        let input = read_input(TEST_INPUT).expect("valid input");
+        let rendered = input.locks.iter().chain(input.keys.iter()).map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(rendered.join("\n\n") + "\n", TEST_INPUT);
+        let lock = Lock { kind: Kind::Lock, pins: vec![0, 5, 3, 4, 3], height: 5 };
+        assert_eq!(Lock::parse(0, &lock.to_string()), Ok(lock));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(read_input("#####").err(), Some(SchematicError::TooShort { block: 0 }));
+        assert_eq!(read_input("###\n.x.\n...").err(), Some(SchematicError::InvalidChar { block: 0, row: 1, c: 'x' }));
+        assert_eq!(read_input("###\n.#\n...").err(), Some(SchematicError::NotRectangular { block: 0, row: 1 }));
+        assert_eq!(read_input("###\n.#.\n.#.").err(), Some(SchematicError::NoSolidRow { block: 0 }));
+        assert_eq!(read_input("###\n.#.\n...\n.#.\n...").err(), Some(SchematicError::BrokenPin { block: 0, column: 1 }));
+        assert_eq!(
+            read_input("###\n.#.\n...\n\n...\n.#.\n.#.\n###").err(),
+            Some(SchematicError::SizeMismatch { block: 1, expected: (3, 3), found: (3, 4) })
+        );
+        assert!(read_input("###\r\n.#.\r\n...\r\n\r\n...\r\n.#.\r\n###\r\n").is_ok());
+    }
+
 }
\ No newline at end of file